@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+pub const ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CgroupStats {
+    pub memory: u64,
+    pub cputime: u64,
+}
+
+pub fn unit_path(root: &Path, unit: &str) -> PathBuf {
+    if unit.contains('/') {
+        root.join(unit.trim_start_matches('/'))
+    } else if unit.contains('.') {
+        root.join("system.slice").join(unit)
+    } else {
+        root.join("system.slice").join(format!("{}.service", unit))
+    }
+}
+
+pub fn procs(path: &Path) -> Vec<u32> {
+    let mut pids: Vec<u32> = read_to_string(path.join("cgroup.procs"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|s| s.trim().parse().ok())
+        .collect();
+    if let Ok(entries) = read_dir(path) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pids.extend(procs(&entry.path()));
+            }
+        }
+    }
+    pids.sort();
+    pids
+}
+
+pub fn stats(path: &Path) -> Option<CgroupStats> {
+    let memory = read_to_string(path.join("memory.current")).ok()?.trim().parse().ok()?;
+    let usec: u64 = read_to_string(path.join("cpu.stat"))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(CgroupStats { memory, cputime: usec / 1_000_000 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cgroupfs")
+    }
+
+    #[test]
+    fn test_unit_path() {
+        let root = Path::new(ROOT);
+        assert_eq!(unit_path(root, "nginx"), root.join("system.slice/nginx.service"));
+        assert_eq!(unit_path(root, "nginx.service"), root.join("system.slice/nginx.service"));
+        assert_eq!(unit_path(root, "docker.socket"), root.join("system.slice/docker.socket"));
+        assert_eq!(unit_path(root, "user.slice/app.service"), root.join("user.slice/app.service"));
+    }
+
+    #[test]
+    fn test_procs() {
+        assert_eq!(procs(&unit_path(&root(), "nginx.service")), [1200, 1201, 1202, 1203]);
+        assert!(procs(&unit_path(&root(), "empty.service")).is_empty());
+        assert!(procs(&unit_path(&root(), "missing.service")).is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = stats(&unit_path(&root(), "nginx")).unwrap();
+        assert_eq!(stats, CgroupStats { memory: 52428800, cputime: 12 });
+        assert_eq!(super::stats(&unit_path(&root(), "empty")), None);
+    }
+}
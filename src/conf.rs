@@ -1,11 +1,17 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::read_to_string;
 use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use serde::Deserialize;
+use regex::{Regex, RegexBuilder};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
 
-use crate::proc::ProcessMap;
+use crate::cgroup;
+use crate::probe::Probes;
+use crate::proc::{self, ProcessInfo, ProcessMap};
 
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -13,6 +19,10 @@ pub enum SortBy {
     Cpu,
     Rss,
     Uptime,
+    Io,
+    Pss,
+    Uss,
+    Swap,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
@@ -22,27 +32,162 @@ pub enum SummaryDisplay {
     All,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceDisplay {
     None,
     Required,
+    #[default]
     All,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    Exact,
+    Glob,
+    Regex,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Ok,
+    Warning,
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub struct Threshold {
+    pub warning: Option<f64>,
+    pub critical: Option<f64>,
+}
+
+impl Threshold {
+    pub fn above(&self, value: f64) -> Level {
+        if self.critical.is_some_and(|c| value > c) {
+            Level::Critical
+        } else if self.warning.is_some_and(|w| value > w) {
+            Level::Warning
+        } else {
+            Level::Ok
+        }
+    }
+
+    pub fn below(&self, value: f64) -> Level {
+        if self.critical.is_some_and(|c| value < c) {
+            Level::Critical
+        } else if self.warning.is_some_and(|w| value < w) {
+            Level::Warning
+        } else {
+            Level::Ok
+        }
+    }
+
+    pub fn limit(&self, level: Level) -> f64 {
+        let limit = if level == Level::Critical { self.critical } else { self.warning };
+        limit.unwrap_or_default()
+    }
+
+    fn merge(&mut self, other: Threshold) {
+        if other.warning.is_some() {
+            self.warning = other.warning;
+        }
+        if other.critical.is_some() {
+            self.critical = other.critical;
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Thresholds {
+    #[serde(default)]
+    pub load: Threshold,
+    #[serde(default)]
+    pub memory_free: Threshold,
+    #[serde(default)]
+    pub swap_used: Threshold,
+    #[serde(default)]
+    pub storage_free: Threshold,
+    #[serde(default)]
+    pub mounts: BTreeMap<String, Threshold>,
+}
+
+impl Thresholds {
+    pub fn storage(&self, mount: &str) -> Threshold {
+        let mut res = self.storage_free;
+        if let Some(t) = self.mounts.get(mount) {
+            res.merge(*t);
+        }
+        res
+    }
+
+    fn merge(&mut self, other: Thresholds) {
+        self.load.merge(other.load);
+        self.memory_free.merge(other.memory_free);
+        self.swap_used.merge(other.swap_used);
+        self.storage_free.merge(other.storage_free);
+        for (mount, t) in other.mounts {
+            self.mounts.entry(mount).or_default().merge(t);
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Limits {
+    #[serde(default, deserialize_with = "de_size")]
+    pub max_rss: Option<u64>,
+    #[serde(default, deserialize_with = "de_secs")]
+    pub max_cputime: Option<u64>,
+    pub max_cpu_percent: Option<f32>,
+    #[serde(default, deserialize_with = "de_secs")]
+    pub min_uptime: Option<u64>,
+    #[serde(default, deserialize_with = "de_secs")]
+    pub max_uptime: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
 pub struct Service {
-    name: String,
+    pub name: String,
     #[serde(default = "default_true")]
-    required: bool,
+    pub required: bool,
+    #[serde(default)]
+    pub multiple: bool,
+    pub min_instances: Option<usize>,
+    pub max_instances: Option<usize>,
+    pub pidfile: Option<String>,
+    pub command: Option<String>,
+    pub exe: Option<String>,
+    pub cwd: Option<String>,
+    pub comm: Option<String>,
+    pub user: Option<String>,
+    pub uid: Option<u32>,
+    #[serde(rename = "match")]
+    pub match_mode: Option<MatchMode>,
+    pub unit: Option<String>,
+    pub container: Option<String>,
+    pub image: Option<String>,
     #[serde(default)]
-    multiple: bool,
-    pidfile: Option<String>,
-    command: Option<String>,
+    pub listen: Vec<u16>,
+    #[serde(flatten)]
+    pub limits: Limits,
+    #[serde(flatten)]
+    pub probes: Probes,
 }
 
 impl Service {
-    pub fn matches(&self, procs: &ProcessMap) -> Vec<(&String, u32, String)> {
+    pub fn matches(&self, procs: &ProcessMap) -> Vec<(&Service, u32, String)> {
+        if let Some(unit) = &self.unit {
+            let pids = cgroup::procs(&cgroup::unit_path(Path::new(cgroup::ROOT), unit));
+            return self.group_matches(procs.services_by_group(&pids));
+        }
+        if self.container.is_some() || self.image.is_some() {
+            if !procs.containers().available() && self.min_instances() > 0 {
+                return vec![(self, 0, String::from("container engine unavailable"))];
+            }
+            let found = procs.containers().find(self.container.as_deref(), self.image.as_deref());
+            let ids: Vec<&str> = found.iter().map(|c| c.id.as_str()).collect();
+            return self.group_matches(procs.services_by_container(&ids));
+        }
         let mut found = vec![];
         let cmd = self.command.as_ref().unwrap_or(&self.name);
         let m1 = self
@@ -51,41 +196,187 @@ impl Service {
             .and_then(|f| read_to_string(f).ok())
             .and_then(|s| s.trim().parse::<u32>().ok())
             .and_then(|p| procs.service_by_pid(&p));
-        let mut m2 = procs.services_by_cmd(cmd);
-        m2.sort();
+        let m2 = if self.has_filters() {
+            match self.filter(procs) {
+                Ok(pids) => pids,
+                Err(_) if self.min_instances() == 0 => return vec![],
+                Err(msg) => return vec![(self, 0, format!("invalid matcher: {}", msg))],
+            }
+        } else {
+            procs.services_by_cmd(cmd)
+        };
         if let Some(m1) = m1 {
-            found.push((&self.name, m1, String::from("")));
-        } else if m2.is_empty() && self.required {
-            found.push((&self.name, 0, String::from("service not running")));
+            found.push((self, m1, String::from("")));
+        } else if m2.is_empty() && self.min_instances() > 0 {
+            found.push((self, 0, String::from("service not running")));
         } else if !m2.is_empty() {
             let mut msg = String::from("");
             if self.pidfile.is_some() {
                 msg = format!("invalid PID file {}", self.pidfile.as_deref().unwrap_or("?"));
-            } else if m2.len() > 1 && !self.multiple {
-                msg = String::from("multiple matching processes");
             }
             for pid in m2 {
-                found.push((&self.name, pid, msg.clone()));
+                found.push((self, pid, msg.clone()));
             }
         }
         found
     }
+
+    fn has_filters(&self) -> bool {
+        self.match_mode.is_some()
+            || self.exe.is_some()
+            || self.cwd.is_some()
+            || self.comm.is_some()
+            || self.user.is_some()
+            || self.uid.is_some()
+    }
+
+    fn filter(&self, procs: &ProcessMap) -> Result<Vec<u32>, String> {
+        let is_match = self.matcher()?;
+        Ok(procs.services_by(is_match))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.has_filters() {
+            return Ok(());
+        }
+        self.matcher().map(|_| ())
+    }
+
+    fn matcher(&self) -> Result<impl Fn(&ProcessInfo) -> bool, String> {
+        let mode = self.match_mode.unwrap_or(MatchMode::Exact);
+        let pattern = |p: Option<&String>| p.map(|p| Pattern::new(mode, p)).transpose();
+        let others = [&self.exe, &self.cwd, &self.comm, &self.user].iter().any(|o| o.is_some()) || self.uid.is_some();
+        let cmd = self.command.as_ref().or((!others).then_some(&self.name));
+        let cmd = match (cmd, self.match_mode) {
+            (Some(cmd), None) => Some(Pattern::legacy(cmd)),
+            (cmd, _) => pattern(cmd)?,
+        };
+        let exe = pattern(self.exe.as_ref())?;
+        let cwd = pattern(self.cwd.as_ref())?;
+        let comm = pattern(self.comm.as_ref())?;
+        let uid = match (&self.user, self.uid) {
+            (_, Some(uid)) => Some(uid),
+            (Some(user), None) => {
+                let uid = user.parse().ok().or_else(|| proc::uid_by_name(user));
+                Some(uid.ok_or(format!("unknown user {}", user))?)
+            }
+            (None, None) => None,
+        };
+        Ok(move |info: &ProcessInfo| {
+            cmd.as_ref().is_none_or(|p| p.is_match(&info.cmd))
+                && exe.as_ref().is_none_or(|p| p.is_match(&info.exe))
+                && cwd.as_ref().is_none_or(|p| p.is_match(&info.cwd))
+                && comm.as_ref().is_none_or(|p| p.is_match(&info.comm))
+                && uid.is_none_or(|uid| info.uid == Some(uid))
+        })
+    }
+
+    fn group_matches(&self, pids: Vec<u32>) -> Vec<(&Service, u32, String)> {
+        let mut found = vec![];
+        if pids.is_empty() && self.min_instances() > 0 {
+            found.push((self, 0, String::from("service not running")));
+        }
+        for pid in pids {
+            found.push((self, pid, String::from("")));
+        }
+        found
+    }
+
+    pub fn min_instances(&self) -> usize {
+        self.min_instances.unwrap_or(if self.required { 1 } else { 0 })
+    }
+
+    pub fn check_instances(&self, count: usize) -> (Level, String) {
+        let min = self.min_instances();
+        let multiple = self.multiple || min > 1;
+        if count > 0 && count < min {
+            (Level::Critical, format!("{} instances below min {}", count, min))
+        } else if let Some(max) = self.max_instances
+            && count > max
+        {
+            (Level::Warning, format!("{} instances above max {}", count, max))
+        } else if self.max_instances.is_none() && !multiple && count > 1 {
+            (Level::Warning, String::from("multiple matching processes"))
+        } else {
+            (Level::Ok, String::new())
+        }
+    }
+}
+
+enum Pattern {
+    Legacy(String, Option<Regex>),
+    Exact(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn legacy(pattern: &str) -> Pattern {
+        let re = RegexBuilder::new(pattern).case_insensitive(true).build().ok();
+        Pattern::Legacy(pattern.to_string(), re)
+    }
+
+    fn new(mode: MatchMode, pattern: &str) -> Result<Pattern, String> {
+        match mode {
+            MatchMode::Exact => Ok(Pattern::Exact(pattern.to_string())),
+            MatchMode::Glob => Ok(Pattern::Glob(pattern.to_string())),
+            MatchMode::Regex => match Regex::new(pattern) {
+                Ok(re) => Ok(Pattern::Regex(re)),
+                Err(_) => Err(format!("invalid regex {}", pattern)),
+            },
+        }
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Legacy(s, re) => value.contains(s.as_str()) || re.as_ref().is_some_and(|r| r.is_match(value)),
+            Pattern::Exact(s) => value == s,
+            Pattern::Glob(s) => glob_match(s, value),
+            Pattern::Regex(re) => re.is_match(value),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Global {
+    format: Option<String>,
+    display_summary: Option<SummaryDisplay>,
+    display_services: Option<ServiceDisplay>,
+    sort: Option<SortBy>,
+    limit: Option<usize>,
+    sample: Option<String>,
+    state: Option<PathBuf>,
+    smaps: Option<bool>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
 }
 
 #[derive(Debug, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    global: Global,
+    #[serde(default)]
+    thresholds: Thresholds,
+    #[serde(default)]
+    services: Vec<Service>,
+    #[serde(flatten)]
+    toplevel: toml::Table,
+}
+
+#[derive(Debug)]
 pub struct Config {
-    #[serde(default = "default_format")]
     pub format: String,
-    #[serde(default = "default_summary_display")]
     pub display_summary: SummaryDisplay,
-    #[serde(default = "default_service_display")]
     pub display_services: ServiceDisplay,
-    #[serde(default)]
     pub sort: Option<SortBy>,
-    #[serde(default)]
     pub limit: Option<usize>,
-    #[serde(default)]
+    pub sample: Option<Duration>,
+    pub state: Option<PathBuf>,
+    pub smaps: bool,
+    pub thresholds: Thresholds,
     pub services: Vec<Service>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
 }
 
 impl Config {
@@ -93,7 +384,7 @@ impl Config {
         let mut merged = Config::empty();
         for path in locate()? {
             if path.extension().and_then(|e| e.to_str()) == Some("toml") {
-                merged.services.append(&mut parse_toml(&path)?.services);
+                merged.merge(parse_toml(&path)?, &path);
             } else {
                 merged.services.extend(parse_legacy(&path)?);
             }
@@ -108,29 +399,173 @@ impl Config {
             display_services: ServiceDisplay::All,
             sort: None,
             limit: None,
+            sample: None,
+            state: None,
+            smaps: false,
+            thresholds: Thresholds::default(),
             services: vec![],
+            warnings: vec![],
+            errors: vec![],
         }
     }
 
-    pub fn service_matches(&self, procs: &ProcessMap) -> Vec<(&String, u32, String)> {
-        self.services.iter().flat_map(|item| item.matches(procs)).collect()
+    fn merge(&mut self, file: ConfigFile, path: &Path) {
+        if !file.toplevel.is_empty() {
+            let keys: Vec<String> = file.toplevel.keys().cloned().collect();
+            match toml::Value::Table(file.toplevel).try_into::<Global>() {
+                Ok(mut legacy) => {
+                    for key in keys.iter().filter(|k| !legacy.unknown.contains_key(*k)) {
+                        self.warnings.push(format!(
+                            "top-level key '{}' in {} is deprecated, move it under [global]",
+                            key,
+                            path.display()
+                        ));
+                    }
+                    for key in legacy.unknown.keys() {
+                        self.warnings
+                            .push(format!("unknown top-level key '{}' in {}", key, path.display()));
+                    }
+                    legacy.unknown.clear();
+                    self.merge_global(legacy, path);
+                }
+                Err(err) => self
+                    .warnings
+                    .push(format!("invalid top-level keys in {}: {}", path.display(), err)),
+            }
+        }
+        self.merge_global(file.global, path);
+        for svc in &file.services {
+            if let Err(msg) = svc.validate() {
+                self.errors
+                    .push(format!("invalid service '{}' in {}: {}", svc.name, path.display(), msg));
+            }
+        }
+        self.thresholds.merge(file.thresholds);
+        self.services.extend(file.services);
+    }
+
+    fn merge_global(&mut self, global: Global, path: &Path) {
+        if let Some(format) = global.format {
+            self.format = format;
+        }
+        if let Some(display) = global.display_summary {
+            self.display_summary = display;
+        }
+        if let Some(display) = global.display_services {
+            self.display_services = display;
+        }
+        if global.sort.is_some() {
+            self.sort = global.sort;
+        }
+        if global.limit.is_some() {
+            self.limit = global.limit;
+        }
+        if let Some(sample) = global.sample {
+            match parse_duration(&sample) {
+                Some(d) => self.sample = Some(d),
+                None => self.warnings.push(format!("invalid sample '{}' in {}", sample, path.display())),
+            }
+        }
+        if let Some(smaps) = global.smaps {
+            self.smaps = smaps;
+        }
+        if global.state.is_some() {
+            self.state = global.state;
+        }
+        for key in global.unknown.keys() {
+            self.warnings
+                .push(format!("unknown [global] key '{}' in {}", key, path.display()));
+        }
     }
 }
 
-fn default_true() -> bool {
-    true
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let pos = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (num, unit) = value.split_at(pos);
+    let num = num.parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0)?;
+    let secs = match unit.trim() {
+        "ms" => num / 1000.0,
+        "" | "s" => num,
+        "m" => num * 60.0,
+        "h" => num * 3600.0,
+        "d" => num * 86400.0,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(secs))
 }
 
-fn default_format() -> String {
-    String::from("text")
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let pos = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (num, unit) = value.split_at(pos);
+    let num = num.parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0)?;
+    let scale = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1_u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    Some((num * scale as f64) as u64)
 }
 
-fn default_summary_display() -> SummaryDisplay {
-    SummaryDisplay::All
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pat, txt): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    while t < txt.len() {
+        if p < pat.len() && (pat[p] == '?' || pat[p] == txt[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pat.len() && pat[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pat[p..].iter().all(|c| *c == '*')
 }
 
-fn default_service_display() -> ServiceDisplay {
-    ServiceDisplay::All
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(u64),
+    String(String),
+}
+
+fn de_size<'de, D: Deserializer<'de>>(de: D) -> Result<Option<u64>, D::Error> {
+    match Option::<NumberOrString>::deserialize(de)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(n)) => Ok(Some(n)),
+        Some(NumberOrString::String(s)) => parse_size(&s)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid size: {}", s))),
+    }
+}
+
+pub(crate) fn de_secs<'de, D: Deserializer<'de>>(de: D) -> Result<Option<u64>, D::Error> {
+    match Option::<NumberOrString>::deserialize(de)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(n)) => Ok(Some(n)),
+        Some(NumberOrString::String(s)) => parse_duration(&s)
+            .map(|d| Some(d.as_secs()))
+            .ok_or_else(|| D::Error::custom(format!("invalid duration: {}", s))),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_format() -> String {
+    String::from("text")
 }
 
 fn parse_legacy(path: &Path) -> Result<Vec<Service>, Error> {
@@ -145,13 +580,13 @@ fn parse_legacy(path: &Path) -> Result<Vec<Service>, Error> {
             let required = !title.starts_with(['-', '*']);
             let multiple = title.starts_with(['+', '*']);
             let command = (!cmd.is_empty()).then_some(cmd);
-            items.push(Service { name, required, multiple, pidfile, command });
+            items.push(Service { name, required, multiple, pidfile, command, ..Default::default() });
         }
     }
     Ok(items)
 }
 
-fn parse_toml(path: &Path) -> Result<Config, Error> {
+fn parse_toml(path: &Path) -> Result<ConfigFile, Error> {
     let data = read_to_string(path)?;
     toml::from_str(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
 }
@@ -261,10 +696,16 @@ mod tests {
         assert_eq!(legacy_items, toml_items, "90-local mapping mismatch");
     }
 
+    fn merge_str(config: &mut Config, toml: &str) {
+        let file: ConfigFile = toml::from_str(toml).unwrap();
+        config.merge(file, Path::new("test.toml"));
+    }
+
     #[test]
     fn test_global_defaults() {
         let path = etc_dir().join("upstate.toml.d/00-system-default.toml");
-        let config = parse_toml(&path).unwrap();
+        let mut config = Config::empty();
+        config.merge(parse_toml(&path).unwrap(), &path);
         assert_eq!(config.format, "text");
         assert_eq!(config.display_summary, SummaryDisplay::All);
         assert_eq!(config.display_services, ServiceDisplay::All);
@@ -275,6 +716,7 @@ mod tests {
     #[test]
     fn test_global_custom() {
         let toml = r#"
+[global]
 format = "json"
 display_summary = "none"
 display_services = "required"
@@ -284,11 +726,262 @@ limit = 10
 [[services]]
 name = "test"
 "#;
-        let config: Config = toml::from_str(toml).unwrap();
+        let mut config = Config::empty();
+        merge_str(&mut config, toml);
         assert_eq!(config.format, "json");
         assert_eq!(config.display_summary, SummaryDisplay::None);
         assert_eq!(config.display_services, ServiceDisplay::Required);
         assert_eq!(config.sort, Some(SortBy::Rss));
         assert_eq!(config.limit, Some(10));
+        assert_eq!(config.services.len(), 1);
+        assert!(config.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_global_merge() {
+        let mut config = Config::empty();
+        merge_str(
+            &mut config,
+            "[global]\nsort = \"cpu\"\nlimit = 5\n\n[[services]]\nname = \"a\"\n",
+        );
+        merge_str(&mut config, "[global]\nsort = \"rss\"\n\n[[services]]\nname = \"b\"\n");
+        assert_eq!(config.sort, Some(SortBy::Rss));
+        assert_eq!(config.limit, Some(5));
+        assert_eq!(config.format, "text");
+        assert_eq!(config.services.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("6h"), Some(Duration::from_secs(21600)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("2x"), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("-1s"), None);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("4k"), Some(4096));
+        assert_eq!(parse_size("4 GiB"), Some(4 << 30));
+        assert_eq!(parse_size("1.5M"), Some(3 << 19));
+        assert_eq!(parse_size("2TB"), Some(2 << 40));
+        assert_eq!(parse_size("4 apples"), None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("nginx:*", "nginx:1.25"));
+        assert!(glob_match("*web*", "nginx_web_1"));
+        assert!(glob_match("web-?", "web-1"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("web", "web-1"));
+        assert!(!glob_match("nginx:*", "redis:7"));
+        assert!(!glob_match("web-?", "web-12"));
+    }
+
+    #[test]
+    fn test_service_limits() {
+        let toml = r#"
+[[services]]
+name = "java"
+max_rss = "4 GiB"
+max_cputime = 3600
+max_cpu_percent = 80
+min_uptime = "5m"
+
+[[services]]
+name = "other"
+"#;
+        let mut config = Config::empty();
+        merge_str(&mut config, toml);
+        let limits = &config.services[0].limits;
+        assert_eq!(limits.max_rss, Some(4 << 30));
+        assert_eq!(limits.max_cputime, Some(3600));
+        assert_eq!(limits.max_cpu_percent, Some(80.0));
+        assert_eq!(limits.min_uptime, Some(300));
+        assert_eq!(limits.max_uptime, None);
+        assert_eq!(config.services[1].limits, Limits::default());
+    }
+
+    #[test]
+    fn test_service_unit() {
+        let mut config = Config::empty();
+        merge_str(
+            &mut config,
+            "[[services]]\nname = \"nginx\"\nunit = \"upstate-test-missing.service\"\n",
+        );
+        let svc = &config.services[0];
+        assert_eq!(svc.unit.as_deref(), Some("upstate-test-missing.service"));
+        let procs = ProcessMap::new(&sysinfo::System::new());
+        let found = svc.matches(&procs);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].1, found[0].2.as_str()), (0, "service not running"));
+    }
+
+    fn info(cmd: &str, exe: &str, uid: u32) -> ProcessInfo {
+        ProcessInfo {
+            cmd: cmd.to_string(),
+            exe: exe.to_string(),
+            cwd: String::from("/srv/app"),
+            comm: String::from("java"),
+            uid: Some(uid),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_service_matcher() {
+        let procs = ProcessMap::new(&sysinfo::System::new());
+        let app = info("/usr/bin/java -Dapp=main -jar app.jar", "/usr/bin/java", 1000);
+        let other = info("/usr/bin/java -Dapp=other -jar app.jar", "/usr/bin/java", 1000);
+        let grep = info("grep -Dapp=main", "/usr/bin/grep", 0);
+        let toml = r#"
+[[services]]
+name = "legacy"
+command = "DAPP=MAIN"
+
+[[services]]
+name = "glob"
+command = "* -Dapp=main *"
+exe = "/usr/bin/java"
+match = "glob"
+
+[[services]]
+name = "regex"
+command = "-Dapp=main\\b"
+uid = 1000
+match = "regex"
+
+[[services]]
+name = "exact"
+comm = "java"
+cwd = "/srv/app"
+user = "0"
+
+[[services]]
+name = "broken"
+command = "("
+match = "regex"
+
+[[services]]
+name = "nobody"
+user = "no-such-user-xyz"
+"#;
+        let mut config = Config::empty();
+        merge_str(&mut config, toml);
+        let matches = |idx: usize| {
+            let is_match = config.services[idx].matcher().unwrap();
+            [&app, &other, &grep].map(is_match)
+        };
+        assert_eq!(matches(0), [true, false, true]);
+        assert_eq!(matches(1), [true, false, false]);
+        assert_eq!(matches(2), [true, false, false]);
+        assert_eq!(matches(3), [false, false, true]);
+        assert!(config.services[4].filter(&procs).is_err());
+        let invalid = config.services[5].matches(&procs);
+        assert_eq!(
+            invalid.iter().map(|(_, pid, msg)| (*pid, msg.as_str())).collect::<Vec<_>>(),
+            [(0, "invalid matcher: unknown user no-such-user-xyz")]
+        );
+        assert!(config.warnings.is_empty());
+        assert_eq!(
+            config.errors,
+            [
+                "invalid service 'broken' in test.toml: invalid regex (",
+                "invalid service 'nobody' in test.toml: unknown user no-such-user-xyz",
+            ]
+        );
+        assert_eq!(config.services[1].match_mode, Some(MatchMode::Glob));
+    }
+
+    #[test]
+    fn test_service_instances() {
+        let toml = r#"
+[[services]]
+name = "single"
+
+[[services]]
+name = "multiple"
+multiple = true
+
+[[services]]
+name = "pool"
+min_instances = 4
+max_instances = 8
+listen = [80, 443]
+
+[[services]]
+name = "optional"
+required = false
+max_instances = 2
+"#;
+        let mut config = Config::empty();
+        merge_str(&mut config, toml);
+        let check = |idx: usize, count: usize| config.services[idx].check_instances(count);
+        assert_eq!(check(0, 1), (Level::Ok, String::new()));
+        assert_eq!(check(0, 2), (Level::Warning, String::from("multiple matching processes")));
+        assert_eq!(check(1, 40), (Level::Ok, String::new()));
+        assert_eq!(check(2, 3), (Level::Critical, String::from("3 instances below min 4")));
+        assert_eq!(check(2, 6), (Level::Ok, String::new()));
+        assert_eq!(check(2, 9), (Level::Warning, String::from("9 instances above max 8")));
+        assert_eq!(check(3, 0), (Level::Ok, String::new()));
+        assert_eq!(check(3, 3), (Level::Warning, String::from("3 instances above max 2")));
+        assert_eq!(config.services[2].min_instances(), 4);
+        assert_eq!(config.services[2].listen, [80, 443]);
+        assert_eq!(config.services[3].min_instances(), 0);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut config = Config::empty();
+        let toml = r#"
+[thresholds]
+load = { warning = 1.0, critical = 2.0 }
+storage_free = { warning = 10, critical = 5 }
+
+[thresholds.mounts."/data"]
+warning = 20
+"#;
+        merge_str(&mut config, toml);
+        merge_str(&mut config, "[thresholds]\nload = { critical = 3.0 }\n");
+        let t = &config.thresholds;
+        assert_eq!(t.load, Threshold { warning: Some(1.0), critical: Some(3.0) });
+        assert_eq!(t.load.above(0.5), Level::Ok);
+        assert_eq!(t.load.above(1.5), Level::Warning);
+        assert_eq!(t.load.above(3.5), Level::Critical);
+        assert_eq!(t.memory_free.below(0.0), Level::Ok);
+        assert_eq!(t.storage("/").below(7.0), Level::Warning);
+        assert_eq!(t.storage("/data").below(15.0), Level::Warning);
+        assert_eq!(t.storage("/data").below(4.0), Level::Critical);
+    }
+
+    #[test]
+    fn test_global_unknown_key() {
+        let mut config = Config::empty();
+        merge_str(&mut config, "[global]\nsort = \"cpu\"\nlimits = 5\n");
+        assert_eq!(config.sort, Some(SortBy::Cpu));
+        assert_eq!(config.warnings, vec!["unknown [global] key 'limits' in test.toml"]);
+    }
+
+    #[test]
+    fn test_global_toplevel() {
+        let mut config = Config::empty();
+        merge_str(&mut config, "sort = \"rss\"\nlimit = 1\nlimits = 5\n\n[global]\nlimit = 3\n");
+        assert_eq!(config.sort, Some(SortBy::Rss));
+        assert_eq!(config.limit, Some(3));
+        assert_eq!(
+            config.warnings,
+            [
+                "top-level key 'limit' in test.toml is deprecated, move it under [global]",
+                "top-level key 'sort' in test.toml is deprecated, move it under [global]",
+                "unknown top-level key 'limits' in test.toml",
+            ]
+        );
     }
 }
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::fs::read_dir;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::conf::glob_match;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Container {
+    pub engine: &'static str,
+    pub id: String,
+    pub name: String,
+    pub image: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiContainer {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    image: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Containers {
+    list: Vec<Container>,
+    available: bool,
+}
+
+impl Containers {
+    pub fn load() -> Containers {
+        let mut res = Containers::default();
+        for engine in ["docker", "podman"] {
+            for path in sockets(engine) {
+                if let Ok(found) = list(engine, &path) {
+                    res.list.extend(found);
+                    res.available = true;
+                }
+            }
+        }
+        res
+    }
+
+    pub fn available(&self) -> bool {
+        self.available
+    }
+
+    pub fn resolve(&self, label: &str) -> Option<&Container> {
+        let (engine, id) = label.split_once('/')?;
+        self.list
+            .iter()
+            .find(|c| c.engine == engine && !id.is_empty() && c.id.starts_with(id))
+    }
+
+    pub fn find(&self, name: Option<&str>, image: Option<&str>) -> Vec<&Container> {
+        self.list
+            .iter()
+            .filter(|c| name.is_none_or(|n| glob_match(n, &c.name)))
+            .filter(|c| image.is_none_or(|i| glob_match(i, &c.image)))
+            .collect()
+    }
+}
+
+fn sockets(engine: &str) -> Vec<PathBuf> {
+    match engine {
+        "docker" => vec![PathBuf::from("/var/run/docker.sock")],
+        "podman" => {
+            let mut res = vec![PathBuf::from("/run/podman/podman.sock")];
+            if let Ok(dirs) = read_dir("/run/user") {
+                res.extend(dirs.flatten().map(|d| d.path().join("podman/podman.sock")));
+            }
+            res
+        }
+        _ => vec![],
+    }
+}
+
+pub fn list(engine: &'static str, socket: &Path) -> Result<Vec<Container>, Error> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(b"GET /containers/json HTTP/1.0\r\nHost: localhost\r\n\r\n")?;
+    let mut res = String::new();
+    stream.read_to_string(&mut res)?;
+    let (head, body) = res
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid HTTP response"))?;
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        return Err(Error::other(format!("HTTP status {}", status)));
+    }
+    let items: Vec<ApiContainer> = serde_json::from_str(body).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let containers = items
+        .into_iter()
+        .map(|c| {
+            let name = c.names.first().map(|n| n.trim_start_matches('/').to_string());
+            let name = name.unwrap_or_else(|| c.id.chars().take(12).collect());
+            Container { engine, id: c.id, name, image: c.image }
+        })
+        .collect();
+    Ok(containers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    const RESPONSE: &str = concat!(
+        "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n",
+        r#"[{"Id":"9cec00b45bcf7f1d3c0a4b4a6cd1ab0a8d6e2f3a1b2c3d4e5f60718293a4b5c6","#,
+        r#""Names":["/nginx_web_1"],"Image":"nginx:1.25","State":"running"},"#,
+        r#"{"Id":"0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef","Image":"redis"}]"#,
+    );
+
+    fn stub_server(name: &str, response: &'static str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("upstate-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            assert!(buf[..n].starts_with(b"GET /containers/json HTTP/1.0\r\n"));
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        path
+    }
+
+    #[test]
+    fn test_list() {
+        let path = stub_server("list", RESPONSE);
+        let containers = list("docker", &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].name, "nginx_web_1");
+        assert_eq!(containers[0].image, "nginx:1.25");
+        assert_eq!(containers[1].name, "0123456789ab");
+    }
+
+    #[test]
+    fn test_list_errors() {
+        assert!(list("docker", Path::new("/nonexistent/docker.sock")).is_err());
+        let path = stub_server("error", "HTTP/1.0 403 Forbidden\r\n\r\n");
+        let err = list("docker", &path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.to_string(), "HTTP status 403");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let path = stub_server("resolve", RESPONSE);
+        let containers = Containers { list: list("docker", &path).unwrap(), available: true };
+        std::fs::remove_file(&path).unwrap();
+        let found = containers.resolve("docker/9cec00b45bcf").map(|c| c.name.as_str());
+        assert_eq!(found, Some("nginx_web_1"));
+        assert_eq!(containers.resolve("docker/ffffffffffff"), None);
+        assert_eq!(containers.resolve("podman/9cec00b45bcf"), None);
+        assert_eq!(containers.resolve("sshd"), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let path = stub_server("find", RESPONSE);
+        let containers = Containers { list: list("docker", &path).unwrap(), available: true };
+        std::fs::remove_file(&path).unwrap();
+        let names = |name, image| containers.find(name, image).iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(Some("nginx_web_1"), None), ["nginx_web_1"]);
+        assert_eq!(names(Some("nginx"), None), Vec::<String>::new());
+        assert_eq!(names(None, Some("nginx:*")), ["nginx_web_1"]);
+        assert_eq!(names(Some("*"), Some("redis")), ["0123456789ab"]);
+        assert_eq!(names(Some("nginx_*"), Some("redis")), Vec::<String>::new());
+    }
+}
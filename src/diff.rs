@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::report::Report;
+
+const MIN_RSS_CHANGE: u64 = 1024 * 1024;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Snapshot {
+    pub memory: Option<MemorySnapshot>,
+    #[serde(default)]
+    pub storage: Vec<StorageSnapshot>,
+    #[serde(default)]
+    pub services: Vec<ServiceSnapshot>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MemorySnapshot {
+    pub free: u64,
+    pub rss: u64,
+    pub cache: u64,
+    pub swap: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StorageSnapshot {
+    pub mount: String,
+    pub used: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ServiceSnapshot {
+    pub pid: u32,
+    pub name: String,
+    #[serde(default)]
+    pub cputime: u64,
+    #[serde(default)]
+    pub rss: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Change {
+    Added { name: String, pid: u32 },
+    Removed { name: String, pid: u32 },
+    Restarted { name: String, pid: u32, old: u32 },
+    Rss { name: String, pid: u32, old: u64, new: u64 },
+    Cputime { name: String, pid: u32, secs: u64 },
+    Storage { mount: String, old: u64, new: u64 },
+    Memory { key: &'static str, old: u64, new: u64 },
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Result<Snapshot, Error> {
+        let text = read_to_string(path)?;
+        let line = text.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+        serde_json::from_str(&text)
+            .or_else(|_| serde_json::from_str(line))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_report(report: &Report) -> Snapshot {
+        serde_json::to_value(report)
+            .and_then(serde_json::from_value)
+            .unwrap_or_default()
+    }
+
+    fn grouped(&self) -> BTreeMap<&str, Vec<&ServiceSnapshot>> {
+        let mut res: BTreeMap<&str, Vec<&ServiceSnapshot>> = BTreeMap::new();
+        for svc in self.services.iter().filter(|s| s.pid != 0) {
+            res.entry(svc.name.as_str()).or_default().push(svc);
+        }
+        res
+    }
+}
+
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let mut res = vec![];
+    if let (Some(old), Some(new)) = (&old.memory, &new.memory) {
+        let pairs = [
+            ("free", old.free, new.free),
+            ("rss", old.rss, new.rss),
+            ("cache", old.cache, new.cache),
+            ("swap", old.swap, new.swap),
+        ];
+        for (key, old, new) in pairs {
+            if old != new {
+                res.push(Change::Memory { key, old, new });
+            }
+        }
+    }
+    for disk in &new.storage {
+        if let Some(prev) = old.storage.iter().find(|d| d.mount == disk.mount)
+            && prev.used != disk.used
+        {
+            res.push(Change::Storage { mount: disk.mount.clone(), old: prev.used, new: disk.used });
+        }
+    }
+    let (before, after) = (old.grouped(), new.grouped());
+    for (name, procs) in &before {
+        if !after.contains_key(name) {
+            res.push(Change::Removed { name: name.to_string(), pid: procs[0].pid });
+        }
+    }
+    for (name, procs) in &after {
+        let name = name.to_string();
+        let pid = procs[0].pid;
+        let Some(prev) = before.get(name.as_str()) else {
+            res.push(Change::Added { name, pid });
+            continue;
+        };
+        let restarted = !procs.iter().any(|p| prev.iter().any(|o| o.pid == p.pid));
+        if restarted {
+            res.push(Change::Restarted { name: name.clone(), pid, old: prev[0].pid });
+        }
+        let rss = |procs: &[&ServiceSnapshot]| procs.iter().map(|p| p.rss).sum::<u64>();
+        let (old, new) = (rss(prev), rss(procs));
+        if old.abs_diff(new) >= MIN_RSS_CHANGE {
+            res.push(Change::Rss { name: name.clone(), pid, old, new });
+        }
+        let cputime = |procs: &[&ServiceSnapshot]| procs.iter().map(|p| p.cputime).sum::<u64>();
+        let secs = if restarted {
+            cputime(procs)
+        } else {
+            cputime(procs).saturating_sub(cputime(prev))
+        };
+        if secs > 0 {
+            res.push(Change::Cputime { name, pid, secs });
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"{"cores":4,"uptime":100,"memory":{"total":1000,"free":500,"rss":300,"cache":200,"swap":0},
+        "storage":[{"total":100,"used":40,"free":60,"dev":"sda1","mount":"/"}],
+        "services":[{"pid":10,"name":"web","cputime":5,"uptime":50,"rss":1048576},
+        {"pid":11,"name":"web","cputime":1,"uptime":50,"rss":1048576},
+        {"pid":20,"name":"db","cputime":100,"uptime":50,"rss":5000},
+        {"pid":30,"name":"cron","cputime":0,"uptime":50,"rss":10},
+        {"pid":0,"name":"missing","error":"service not running"}]}"#;
+
+    const NEW: &str = r#"{"cores":4,"uptime":200,"memory":{"total":1000,"free":400,"rss":400,"cache":200,"swap":0},
+        "storage":[{"total":100,"used":45,"free":55,"dev":"sda1","mount":"/"}],
+        "services":[{"pid":11,"name":"web","cputime":3,"uptime":150,"rss":1048576},
+        {"pid":12,"name":"web","cputime":4,"uptime":10,"rss":2097152},
+        {"pid":21,"name":"db","cputime":7,"uptime":10,"rss":5000},
+        {"pid":40,"name":"sys/foo","cputime":0,"uptime":10,"rss":10}]}"#;
+
+    #[test]
+    fn test_diff() {
+        let old: Snapshot = serde_json::from_str(OLD).unwrap();
+        let new: Snapshot = serde_json::from_str(NEW).unwrap();
+        let changes = diff(&old, &new);
+        let web = String::from("web");
+        assert_eq!(
+            changes,
+            [
+                Change::Memory { key: "free", old: 500, new: 400 },
+                Change::Memory { key: "rss", old: 300, new: 400 },
+                Change::Storage { mount: String::from("/"), old: 40, new: 45 },
+                Change::Removed { name: String::from("cron"), pid: 30 },
+                Change::Restarted { name: String::from("db"), pid: 21, old: 20 },
+                Change::Cputime { name: String::from("db"), pid: 21, secs: 7 },
+                Change::Added { name: String::from("sys/foo"), pid: 40 },
+                Change::Rss { name: web.clone(), pid: 11, old: 2097152, new: 3145728 },
+                Change::Cputime { name: web, pid: 11, secs: 1 },
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("upstate-diff-{}.jsonl", std::process::id()));
+        std::fs::write(&path, format!("{}\n{}\n", OLD.replace('\n', ""), NEW.replace('\n', ""))).unwrap();
+        let snapshot = Snapshot::load(&path).unwrap();
+        std::fs::write(&path, "not json").unwrap();
+        let err = Snapshot::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.services.len(), 4);
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
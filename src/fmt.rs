@@ -1,80 +1,222 @@
 use colored::Colorize;
 use core::fmt::Display;
+use serde::Serialize;
 
-pub enum Format {
+use crate::conf::Level;
+
+pub struct Metric {
+    name: String,
+    kind: &'static str,
+    help: &'static str,
+    samples: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct Nagios {
+    services: usize,
+    warnings: Vec<String>,
+    errors: Vec<String>,
+    perfdata: Vec<String>,
+}
+
+pub enum Mode {
     Text,
-    Json { sep: bool, depth: usize },
+    Json,
+    Prometheus(Vec<Metric>),
+    Nagios(Nagios),
+}
+
+pub struct Format {
+    pub mode: Mode,
+    out: String,
 }
 
 impl Format {
+    pub fn text() -> Format {
+        Format { mode: Mode::Text, out: String::new() }
+    }
+
     pub fn json() -> Format {
-        Format::Json { sep: false, depth: 0 }
+        Format { mode: Mode::Json, out: String::new() }
     }
 
-    pub fn text_summary(&self, key: &str, value: &str, detail: &str) {
-        if let Format::Text = *self {
-            println!("{:<10}{value:<26} {}", key.white(), detail.white());
+    pub fn prometheus() -> Format {
+        Format { mode: Mode::Prometheus(vec![]), out: String::new() }
+    }
+
+    pub fn nagios() -> Format {
+        Format { mode: Mode::Nagios(Nagios::default()), out: String::new() }
+    }
+
+    pub fn text_summary(&mut self, key: &str, value: &str, detail: &str, level: Level) {
+        if let Mode::Text = self.mode {
+            let value = format!("{value:<26}");
+            let value = match level {
+                Level::Ok => value.normal(),
+                Level::Warning => value.yellow(),
+                Level::Critical => value.red(),
+            };
+            let line = format!("{:<10}{} {}\n", key.white(), value, detail.white());
+            self.out.push_str(&line);
         }
     }
 
-    pub fn text_proc_ok(&self, label: String, detail: String) {
-        if let Format::Text = *self {
-            println!("{} {label:<34} {}", "\u{25CF}".green(), detail.white());
+    pub fn text_alert(&mut self, level: Level, message: &str) {
+        if let Mode::Text = self.mode {
+            let line = match level {
+                Level::Ok => return,
+                Level::Warning => format!("  {} {}\n", "Warning:".yellow(), message),
+                Level::Critical => format!("  {} {}\n", "Error:".red(), message),
+            };
+            self.out.push_str(&line);
         }
     }
 
-    pub fn text_proc_warn(&self, label: String, detail: String) {
-        if let Format::Text = *self {
-            println!("{} {label:<34} {}", "\u{25A0}".yellow(), detail.white());
+    pub fn text_proc_ok(&mut self, label: String, detail: String) {
+        if let Mode::Text = self.mode {
+            let line = format!("{} {label:<34} {}\n", "\u{25CF}".green(), detail.white());
+            self.out.push_str(&line);
         }
     }
 
-    pub fn text_proc_err(&self, label: String, detail: String) {
-        if let Format::Text = *self {
-            println!("{} {label:<34} {}", "\u{25A0}".red(), detail);
+    pub fn text_proc_warn(&mut self, label: String, detail: String) {
+        if let Mode::Text = self.mode {
+            let line = format!("{} {label:<34} {}\n", "\u{25A0}".yellow(), detail.white());
+            self.out.push_str(&line);
         }
     }
 
-    pub fn text_proc_more(&self, label: &str, message: String) {
-        if let Format::Text = *self {
-            println!("  {} {}", label.yellow(), message);
+    pub fn text_proc_err(&mut self, label: String, detail: String) {
+        if let Mode::Text = self.mode {
+            let line = format!("{} {label:<34} {}\n", "\u{25A0}".red(), detail);
+            self.out.push_str(&line);
         }
     }
 
-    pub fn json_open(&mut self, name: &str, array: bool, _newline: bool) {
-        if let Format::Json { sep, depth } = *self {
-            if sep {
-                print!(",");
-            }
-            if !name.is_empty() {
-                print!("\"{name}\":");
+    pub fn text_proc_child(&mut self, depth: usize, label: String, detail: String) {
+        if let Mode::Text = self.mode {
+            let indent = "  ".repeat(depth.saturating_sub(1));
+            let label = format!("{}\u{2514} {}", indent, label);
+            let line = format!("  {label:<34} {}\n", detail.white());
+            self.out.push_str(&line);
+        }
+    }
+
+    pub fn json_report<T: Serialize>(&mut self, value: &T) {
+        if let Mode::Json = self.mode
+            && let Ok(json) = serde_json::to_string(value)
+        {
+            self.out.push_str(&json);
+            self.out.push('\n');
+        }
+    }
+
+    pub fn prom_metric<T: Display>(
+        &mut self,
+        name: &str,
+        kind: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: T,
+    ) {
+        if let Mode::Prometheus(metrics) = &mut self.mode {
+            let name = format!("upstate_{name}");
+            let pos = metrics.iter().position(|m| m.name == name).unwrap_or_else(|| {
+                metrics.push(Metric { name, kind, help, samples: vec![] });
+                metrics.len() - 1
+            });
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", prom_escape(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let metric = &mut metrics[pos];
+            if !metric.samples.iter().any(|(l, _)| *l == labels) {
+                metric.samples.push((labels, value.to_string()));
             }
-            print!("{}", if array { "[" } else { "{" });
-            *self = Format::Json { sep: false, depth: depth + 1 };
         }
     }
 
-    pub fn json_close(&mut self, array: bool) {
-        print!("{}", if array { "]" } else { "}" });
-        if let Format::Json { depth, .. } = *self {
-            *self = Format::Json { sep: true, depth: depth - 1 };
-            if depth == 1 {
-                println!();
+    pub fn nagios_perf<T: Display>(&mut self, label: &str, value: T, uom: &str, max: Option<u64>) {
+        if let Mode::Nagios(nagios) = &mut self.mode {
+            let label = label.replace('\'', "''");
+            let range = max.map(|m| format!(";;;0;{m}")).unwrap_or_default();
+            nagios.perfdata.push(format!("'{label}'={value}{uom}{range}"));
+        }
+    }
+
+    pub fn nagios_service(&mut self, name: &str, error: bool, msg: &str) {
+        if let Mode::Nagios(nagios) = &mut self.mode {
+            if error {
+                nagios.errors.push(format!("{name}: {msg}"));
+            } else {
+                if !msg.is_empty() {
+                    nagios.warnings.push(format!("{name}: {msg}"));
+                }
+                nagios.services += 1;
             }
         }
     }
 
-    pub fn json_key_val<T: Display>(&mut self, key: &str, value: T) {
-        if let Format::Json { sep, depth } = *self {
-            if sep {
-                print!(",");
+    pub fn nagios_alert(&mut self, level: Level, msg: &str) {
+        if let Mode::Nagios(nagios) = &mut self.mode {
+            match level {
+                Level::Ok => {}
+                Level::Warning => nagios.warnings.push(msg.to_string()),
+                Level::Critical => nagios.errors.push(msg.to_string()),
             }
-            print!("\"{key}\":{value}");
-            *self = Format::Json { sep: true, depth };
         }
     }
 
-    pub fn json_key_str<T: Display>(&mut self, key: &str, value: T) {
-        self.json_key_val(key, format!("\"{}\"", value));
+    pub fn nagios_code(&self) -> Option<i32> {
+        match &self.mode {
+            Mode::Nagios(nagios) if !nagios.errors.is_empty() => Some(2),
+            Mode::Nagios(nagios) if !nagios.warnings.is_empty() => Some(1),
+            Mode::Nagios(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn finish(&mut self) -> String {
+        match &self.mode {
+            Mode::Prometheus(metrics) => {
+                for m in metrics {
+                    self.out.push_str(&format!("# HELP {} {}\n", m.name, m.help));
+                    self.out.push_str(&format!("# TYPE {} {}\n", m.name, m.kind));
+                    for (labels, value) in &m.samples {
+                        if labels.is_empty() {
+                            self.out.push_str(&format!("{} {}\n", m.name, value));
+                        } else {
+                            self.out.push_str(&format!("{}{{{}}} {}\n", m.name, labels, value));
+                        }
+                    }
+                }
+            }
+            Mode::Nagios(nagios) => {
+                let (status, detail) = if !nagios.errors.is_empty() {
+                    ("CRITICAL", nagios.errors.join(", "))
+                } else if !nagios.warnings.is_empty() {
+                    ("WARNING", nagios.warnings.join(", "))
+                } else {
+                    ("OK", format!("{} services running", nagios.services))
+                };
+                self.out.push_str(&format!("UPSTATE {} - {}", status, detail));
+                if !nagios.perfdata.is_empty() {
+                    self.out.push_str(&format!(" | {}", nagios.perfdata.join(" ")));
+                }
+                self.out.push('\n');
+            }
+            _ => {}
+        }
+        std::mem::take(&mut self.out)
     }
 }
+
+pub fn nagios_unknown<T: Display>(msg: T) -> i32 {
+    println!("UPSTATE UNKNOWN - {msg}");
+    3
+}
+
+fn prom_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,184 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{OpenOptions, create_dir_all, metadata, read_dir, read_to_string, remove_file};
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+
+use crate::report::Report;
+
+pub const DIR: &str = "/var/lib/upstate/history";
+
+const PREFIX: &str = "upstate-";
+const SUFFIX: &str = ".jsonl";
+const SPARKS: [char; 8] = [
+    '\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}', '\u{2588}',
+];
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: u64,
+    #[serde(flatten)]
+    report: &'a Report,
+}
+
+pub struct Recorder {
+    pub dir: PathBuf,
+    pub max_size: u64,
+    pub keep: usize,
+}
+
+impl Recorder {
+    pub fn append(&self, report: &Report, now: u64) -> Result<PathBuf, Error> {
+        let line = serde_json::to_string(&Record { time: now, report }).map_err(Error::other)?;
+        let today = &datetime(now)[..10];
+        create_dir_all(&self.dir)?;
+        let path = match files(&self.dir)?.pop() {
+            Some(path) if file_date(&path) == Some(today) && metadata(&path)?.len() < self.max_size => path,
+            _ => {
+                let stamp = datetime(now).replace(' ', "T").replace(':', "");
+                self.dir.join(format!("{}{}{}", PREFIX, stamp, SUFFIX))
+            }
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        let all = files(&self.dir)?;
+        for old in &all[..all.len().saturating_sub(self.keep.max(1))] {
+            remove_file(old)?;
+        }
+        Ok(path)
+    }
+}
+
+pub fn files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut res: Vec<PathBuf> = read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with(PREFIX) && name.ends_with(SUFFIX)
+        })
+        .collect();
+    res.sort();
+    Ok(res)
+}
+
+fn file_date(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?.strip_prefix(PREFIX)?;
+    name.get(..10)
+}
+
+pub fn query(dir: &Path, service: Option<&str>, field: &str, since: u64) -> Result<Vec<(u64, f64)>, Error> {
+    let pointer = format!("/{}", field.replace('.', "/"));
+    let mut res = vec![];
+    for path in files(dir)? {
+        for line in read_to_string(&path)?.lines() {
+            let Ok(record) = serde_json::from_str::<Value>(line) else {
+                continue;
+            };
+            let time = record["time"].as_u64().unwrap_or(0);
+            if time < since {
+                continue;
+            }
+            let value = match service {
+                Some(name) => record["services"].as_array().and_then(|items| {
+                    let values: Vec<f64> = items
+                        .iter()
+                        .filter(|i| i["name"] == name)
+                        .filter_map(|i| i.pointer(&pointer).and_then(Value::as_f64))
+                        .collect();
+                    (!values.is_empty()).then(|| values.iter().sum())
+                }),
+                None => record.pointer(&pointer).and_then(Value::as_f64),
+            };
+            if let Some(value) = value {
+                res.push((time, value));
+            }
+        }
+    }
+    Ok(res)
+}
+
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|v| {
+            let pos = if max > min { (v - min) / (max - min) * 7.0 } else { 0.0 };
+            SPARKS[pos.round() as usize]
+        })
+        .collect()
+}
+
+pub fn datetime(epoch: u64) -> String {
+    // UTC civil date from days since epoch (Howard Hinnant's algorithm)
+    let days = (epoch / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let secs = epoch % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::tests::item;
+    use crate::report::{IoUsage, ProcStats};
+
+    fn report(rss: u64) -> Report {
+        let io = IoUsage { written: rss / 100, ..Default::default() };
+        let item = item("nginx", 42, Some(ProcStats { rss, io, ..Default::default() }));
+        Report { summary: None, services: Some(vec![item]), warnings: vec![] }
+    }
+
+    #[test]
+    fn test_datetime() {
+        assert_eq!(datetime(0), "1970-01-01 00:00:00");
+        assert_eq!(datetime(951782400), "2000-02-29 00:00:00");
+        assert_eq!(datetime(1792211951), "2026-10-17 04:39:11");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[1.0, 8.0, 4.5]), "\u{2581}\u{2588}\u{2585}");
+        assert_eq!(sparkline(&[3.0, 3.0]), "\u{2581}\u{2581}");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_record_query() {
+        let dir = std::env::temp_dir().join(format!("upstate-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let recorder = Recorder { dir: dir.clone(), max_size: 1 << 20, keep: 2 };
+        let day = 86400 * 20000;
+        let first = recorder.append(&report(100), day + 60).unwrap();
+        assert_eq!(recorder.append(&report(200), day + 120).unwrap(), first);
+        recorder.append(&report(300), day + 86400).unwrap();
+        let small = Recorder { max_size: 1, ..recorder };
+        small.append(&report(400), day + 86401).unwrap();
+        let names = files(&dir).unwrap();
+        let series = query(&dir, Some("nginx"), "rss", day + 86400).unwrap();
+        let missing = query(&dir, Some("nginx"), "cpu", 0).unwrap();
+        let written = query(&dir, Some("nginx"), "io.written", day + 86400).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names.len(), 2);
+        assert!(names[0].ends_with("upstate-2024-10-05T000000.jsonl"), "{:?}", names);
+        assert_eq!(series, [(day + 86400, 300.0), (day + 86401, 400.0)]);
+        assert!(missing.is_empty());
+        assert_eq!(written, [(day + 86400, 3.0), (day + 86401, 4.0)]);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod cgroup;
+pub mod conf;
+pub mod container;
+pub mod diff;
+pub mod history;
+pub mod net;
+pub mod probe;
+pub mod proc;
+pub mod report;
+pub mod state;
+
+pub use conf::{Config, Service};
+pub use proc::ProcessMap;
+pub use report::{Options, Report};
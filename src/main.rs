@@ -1,27 +1,21 @@
 use colored::Colorize;
 use humansize::{BINARY, FormatSizeOptions, format_size};
 use indoc::indoc;
-use std::collections;
 use std::fmt::Display;
+use std::path::PathBuf;
 use std::process;
-use std::time;
-use sysinfo::*;
+use std::time::Duration;
+use sysinfo::System;
 
-mod conf;
-mod fmt;
-mod proc;
-
-use conf::SortBy;
+use upstate::conf::{self, Level};
+use upstate::diff::{self, Change, Snapshot};
+use upstate::history::{self, Recorder};
+use upstate::report::{Alerts, Memory, Options, Report, ServiceItem, Storage, Summary, elapsed, refresh};
 
-struct ProcItem {
-    pid: u32,
-    name: String,
-    cpu: u64,
-    rss: u64,
-    uptime: u64,
-    warn: bool,
-    msg: String,
-}
+mod fmt;
+mod record;
+mod serve;
+mod watch;
 
 fn error<T: Display>(msg: T) {
     eprintln!("\n{}: {}", "ERROR".red(), msg);
@@ -36,18 +30,50 @@ fn usage() {
         Prints a machine and service status report.
 
         Syntax: upstate [options]
+                upstate serve [--listen=<addr>] [options]
+                upstate diff <old.json> <new.json>
+                upstate record [--interval=<time>] [--dir=<path>] [options]
+                upstate history [--service=<name>] [--field=<key>] [--since=<time>]
 
         Options:
-          --no-summary  Exclude machine status from output.
-          --no-services Exclude services list from output.
-          --limited     Include machine status and configured services.
-          --complete    Include machine status and all services (default).
-          --sort=<key>  Sort services by cpu, rss, or uptime.
-          --limit=<n>   Limit the number of services shown.
-          --json        Print the report in JSON output format.
+          --no-summary    Exclude machine status from output.
+          --no-services   Exclude services list from output.
+          --limited       Include machine status and configured services.
+          --complete      Include machine status and all services (default).
+          --sort=<key>    Sort services by cpu, rss, uptime, io, pss, uss, or swap.
+          --limit=<n>     Limit the number of services shown.
+          --sample=<time> Sample CPU usage over an interval (e.g. 2s).
+          --tree          Include the child processes of each service.
+          --smaps         Include PSS, USS and swap memory for each service.
+          --state=<file>  Track service restarts and new services in a state file.
+          --json          Print the report in JSON output format.
+          --format=<fmt>  Print the report in text, json, prometheus, or nagios format.
+          --nagios        Print a Nagios plugin status line with perfdata.
+          --watch[=<secs>] Redraw the text report every few seconds (default 2).
+          --listen=<addr> Serve HTTP on this address (default 127.0.0.1:9101).
+          --diff-against=<file> Print changes since a previous JSON report.
+
+        Commands:
+          serve           Run an HTTP server with /metrics, /health and
+                          /report.json endpoints.
+          diff            Print changes between two JSON reports.
+          record          Append a JSON report to a history file every interval.
+          history         Print a time series or sparkline from the history files.
+
+        Record & history options:
+          --interval=<time> Time between recorded reports (default 60s).
+          --dir=<path>    History directory (default /var/lib/upstate/history).
+          --keep=<n>      Number of history files to keep (default 7).
+          --max-size=<size> Rotate history files above this size (default 100M).
+          --service=<name> Service to query (default machine summary).
+          --field=<key>   Field to query, e.g. rss, cputime or memory.free.
+          --since=<time>  Time period to query (default 6h).
+          --sparkline     Print only the sparkline, not the time series.
 
         Returns:
-          Non-zero if one or more configured services were missing.
+          Non-zero if one or more configured services were missing, or if a
+          critical threshold was breached. In Nagios mode 0, 1, 2 or 3 for
+          OK, WARNING, CRITICAL or UNKNOWN.
 
         Files:
           etc/upstate.toml
@@ -58,22 +84,49 @@ fn usage() {
 }
 
 fn main() {
-    let config = conf::Config::new().unwrap_or_else(|err| {
-        warning(err);
-        conf::Config::empty()
+    let (config, config_err) = match conf::Config::new() {
+        Ok(config) => (config, None),
+        Err(err) => (conf::Config::empty(), Some(err)),
+    };
+    for msg in &config.warnings {
+        warning(msg);
+    }
+    for msg in &config.errors {
+        warning(msg);
+    }
+    let mut opts = Options::new(&config);
+    let mut fmt = parse_format(&config.format).unwrap_or_else(|| {
+        warning(format!("invalid format in config: {}", config.format));
+        fmt::Format::text()
     });
-    let mut summary = config.display_summary != conf::SummaryDisplay::None;
-    let mut services_mode = config.display_services;
-    let mut fmt = if config.format == "json" { fmt::Format::json() } else { fmt::Format::Text };
-    let mut sort = config.sort;
-    let mut limit = config.limit;
-    for arg in std::env::args().skip(1) {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let command = if args.first().is_some_and(|s| !s.starts_with('-')) {
+        Some(args.remove(0))
+    } else {
+        None
+    };
+    let mut listen = String::from("127.0.0.1:9101");
+    let mut interval = None;
+    let mut files = vec![];
+    let mut every = Duration::from_secs(60);
+    let mut recorder = Recorder { dir: PathBuf::from(history::DIR), max_size: 100 << 20, keep: 7 };
+    let mut query = record::Query {
+        service: None,
+        field: String::from("rss"),
+        since: Duration::from_secs(6 * 3600),
+        sparkline: false,
+    };
+    for arg in args {
         match arg.as_str() {
-            "--no-summary" => summary = false,
-            "--no-services" => services_mode = conf::ServiceDisplay::None,
-            "--limited" => services_mode = conf::ServiceDisplay::Required,
-            "--complete" => services_mode = conf::ServiceDisplay::All,
+            "--no-summary" => opts.summary = false,
+            "--no-services" => opts.services = conf::ServiceDisplay::None,
+            "--limited" => opts.services = conf::ServiceDisplay::Required,
+            "--complete" => opts.services = conf::ServiceDisplay::All,
             "--json" => fmt = fmt::Format::json(),
+            "--nagios" => fmt = fmt::Format::nagios(),
+            "--watch" => interval = Some(2),
+            "--tree" => opts.tree = true,
+            "--smaps" => opts.smaps = true,
             "--help" | "-h" | "-?" => {
                 usage();
                 process::exit(0);
@@ -83,11 +136,57 @@ fn main() {
                 eprintln!("# Server metrics for man & machine. See --help for details.");
                 process::exit(0);
             }
+            s if s.starts_with("--format=") => {
+                fmt = parse_format(s.trim_start_matches("--format=")).unwrap_or_else(|| {
+                    error(format!("invalid format option: {}", s));
+                    process::exit(1);
+                });
+            }
+            s if s.starts_with("--watch=") => {
+                interval = match s.trim_start_matches("--watch=").parse() {
+                    Ok(n) => Some(n),
+                    Err(_) => {
+                        error(format!("invalid watch option: {}", s));
+                        process::exit(1);
+                    }
+                };
+            }
+            s if s.starts_with("--sample=") => {
+                opts.sample = match conf::parse_duration(s.trim_start_matches("--sample=")) {
+                    Some(d) => Some(d),
+                    None => {
+                        error(format!("invalid sample option: {}", s));
+                        process::exit(1);
+                    }
+                };
+            }
+            s if s.starts_with("--state=") => {
+                opts.state = Some(PathBuf::from(s.trim_start_matches("--state=")));
+            }
+            s if s.starts_with("--diff-against=") => {
+                files = vec![s.trim_start_matches("--diff-against=").to_string()];
+            }
+            s if !s.starts_with('-') && command.as_deref() == Some("diff") => files.push(s.to_string()),
+            s if s.starts_with("--interval=") => every = parse_arg(s, conf::parse_duration),
+            s if s.starts_with("--since=") => query.since = parse_arg(s, conf::parse_duration),
+            s if s.starts_with("--max-size=") => recorder.max_size = parse_arg(s, conf::parse_size),
+            s if s.starts_with("--keep=") => recorder.keep = parse_arg(s, |v| v.parse().ok()),
+            s if s.starts_with("--dir=") => recorder.dir = PathBuf::from(s.trim_start_matches("--dir=")),
+            s if s.starts_with("--service=") => query.service = Some(s.trim_start_matches("--service=").to_string()),
+            s if s.starts_with("--field=") => query.field = s.trim_start_matches("--field=").to_string(),
+            "--sparkline" => query.sparkline = true,
+            s if s.starts_with("--listen=") => {
+                listen = s.trim_start_matches("--listen=").to_string();
+            }
             s if s.starts_with("--sort=") => {
-                sort = match s.trim_start_matches("--sort=") {
+                opts.sort = match s.trim_start_matches("--sort=") {
                     "cpu" => Some(conf::SortBy::Cpu),
                     "rss" | "mem" => Some(conf::SortBy::Rss),
                     "time" | "uptime" => Some(conf::SortBy::Uptime),
+                    "io" => Some(conf::SortBy::Io),
+                    "pss" => Some(conf::SortBy::Pss),
+                    "uss" => Some(conf::SortBy::Uss),
+                    "swap" => Some(conf::SortBy::Swap),
                     _ => {
                         error(format!("invalid sort option: {}", s));
                         process::exit(1);
@@ -95,7 +194,7 @@ fn main() {
                 };
             }
             s if s.starts_with("--limit=") => {
-                limit = match s.trim_start_matches("--limit=").parse() {
+                opts.limit = match s.trim_start_matches("--limit=").parse() {
                     Ok(n) => Some(n),
                     Err(_) => {
                         error(format!("invalid limit option: {}", s));
@@ -110,241 +209,341 @@ fn main() {
             }
         }
     }
-    let sys = System::new_all();
-    fmt.json_open("", false, true);
-    if summary {
-        cpusummary(&sys, &mut fmt);
-        memsummary(&sys, &mut fmt);
-        storagesummary(&mut fmt);
+    if let Some(err) = config_err {
+        if let fmt::Mode::Nagios(_) = fmt.mode {
+            process::exit(fmt::nagios_unknown(err));
+        }
+        warning(err);
+    }
+    if let fmt::Mode::Nagios(_) = fmt.mode
+        && !config.errors.is_empty()
+    {
+        process::exit(fmt::nagios_unknown(config.errors.join(", ")));
     }
-    let mut ret = 0;
-    if services_mode != conf::ServiceDisplay::None {
-        let show_all = services_mode == conf::ServiceDisplay::All;
-        ret = procsummary(&sys, &mut fmt, &config, show_all, sort, limit);
+    match (command.as_deref(), interval) {
+        (None, Some(secs)) => watch::watch(&config, &mut opts, secs),
+        (None, None) if !files.is_empty() => {
+            let mut sys = System::new();
+            refresh(&mut sys, opts.sample);
+            let current = Snapshot::from_report(&Report::new(&sys, &config, &opts));
+            printdiff(&mut fmt, &diff::diff(&load(&files[0]), &current));
+            print!("{}", fmt.finish());
+        }
+        (None, None) => {
+            let mut sys = System::new();
+            refresh(&mut sys, opts.sample);
+            let ret = report(&sys, &mut fmt, &config, &opts);
+            print!("{}", fmt.finish());
+            process::exit(fmt.nagios_code().unwrap_or(ret));
+        }
+        (Some("serve"), _) => {
+            if let Err(err) = serve::serve(&listen, &config, &opts) {
+                error(format!("failed to serve on {}: {}", listen, err));
+                process::exit(1);
+            }
+        }
+        (Some("record"), _) => record::record(&config, &opts, &recorder, every),
+        (Some("history"), _) => {
+            let ret = record::history(&mut fmt, &recorder.dir, &query);
+            print!("{}", fmt.finish());
+            process::exit(ret);
+        }
+        (Some("diff"), _) if files.len() == 2 => {
+            printdiff(&mut fmt, &diff::diff(&load(&files[0]), &load(&files[1])));
+            print!("{}", fmt.finish());
+        }
+        (Some("diff"), _) => {
+            usage();
+            error("diff requires an old and a new JSON report file");
+            process::exit(1);
+        }
+        (Some(unknown), _) => {
+            usage();
+            error(format!("invalid command: {}", unknown));
+            process::exit(1);
+        }
     }
-    fmt.json_close(false);
-    process::exit(ret);
 }
 
-fn elapsed(secs: u64) -> String {
-    let mins = secs / 60;
-    let hours = mins / 60;
-    let days = hours / 24;
-    if days > 0 {
-        format!("{} days", days)
-    } else {
-        format!("{:02}:{:02}:{:02}", hours, mins % 60, secs % 60)
+pub fn report(sys: &System, fmt: &mut fmt::Format, config: &conf::Config, opts: &Options) -> i32 {
+    let report = Report::new(sys, config, opts);
+    for msg in &report.warnings {
+        warning(msg);
+    }
+    fmt.json_report(&report);
+    if let Some(summary) = &report.summary {
+        cpusummary(fmt, summary);
+        memsummary(fmt, &summary.memory);
+        storagesummary(fmt, &summary.storage);
+    }
+    if let Some(services) = &report.services {
+        for item in services {
+            metricitem(fmt, item);
+            printitem(fmt, item);
+        }
     }
+    report.errors() as i32
 }
 
-fn cpusummary(sys: &System, fmt: &mut fmt::Format) {
-    let cores = System::physical_core_count().unwrap_or(1);
-    let uptime = System::uptime();
-    let loadavg = System::load_average();
-    let load = format!("{:.2}, {:.2}, {:.2}", loadavg.one, loadavg.five, loadavg.fifteen);
-    let procs = sys.processes().len();
-    let detail = [
-        format!("up {}", elapsed(uptime)),
-        format!("{} processes", procs),
-        format!("{} cores", cores),
-    ];
-    fmt.text_summary("loadavg:", &load, &detail.join(" \u{2219} "));
-    fmt.json_key_val("cores", cores);
-    fmt.json_key_val("uptime", uptime);
-    fmt.json_key_val("loadavg", format!("[{}]", &load));
-    fmt.json_key_val("processes", procs);
+fn parse_arg<T>(arg: &str, parse: impl Fn(&str) -> Option<T>) -> T {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+    parse(value).unwrap_or_else(|| {
+        error(format!("invalid {} option: {}", name.trim_start_matches("--"), arg));
+        process::exit(1);
+    })
 }
 
-fn memsummary(sys: &System, fmt: &mut fmt::Format) {
-    let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
-    let total = sys.total_memory();
-    let free = sys.free_memory();
-    let freepct = 100_f64 * free as f64 / total as f64;
-    let cache = sys.available_memory().saturating_sub(free);
-    let rss = sys.used_memory();
-    let swap = sys.used_swap();
-    let mem = format!("{} ({:.1}%) free", format_size(free, sizefmt), freepct);
-    let mut detail = vec![
-        format!("{} rss", format_size(rss, sizefmt)),
-        format!("{} cache", format_size(cache, sizefmt)),
-        format!("{} total", format_size(total, sizefmt)),
-    ];
-    if swap > 0 {
-        detail.insert(2, format!("{} swap", format_size(swap, sizefmt)));
-    }
-    fmt.text_summary("memory:", &mem, &detail.join(" \u{2219} "));
-    fmt.json_open("memory", false, true);
-    fmt.json_key_val("total", total);
-    fmt.json_key_val("free", free);
-    fmt.json_key_val("rss", rss);
-    fmt.json_key_val("cache", cache);
-    fmt.json_key_val("swap", swap);
-    fmt.json_close(false);
+fn load(path: &str) -> Snapshot {
+    Snapshot::load(std::path::Path::new(path)).unwrap_or_else(|err| {
+        error(format!("failed to read report {}: {}", path, err));
+        process::exit(1);
+    })
 }
 
-fn storagesummary(fmt: &mut fmt::Format) {
+fn printdiff(fmt: &mut fmt::Format, changes: &[Change]) {
+    fmt.json_report(&changes);
     let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
-    let mut devices = collections::HashSet::new();
-    fmt.json_open("storage", true, true);
-    let disks = Disks::new_with_refreshed_list();
-    for disk in disks.list() {
-        if let DiskKind::Unknown(_) = disk.kind() {
-            continue;
-        } else if devices.contains(disk.name()) {
-            continue;
-        } else {
-            devices.insert(disk.name());
+    let size = |bytes| format_size(bytes, sizefmt);
+    let delta = |old: u64, new: u64| {
+        let sign = if new < old { '-' } else { '+' };
+        format!("{}{}", sign, format_size(old.abs_diff(new), sizefmt))
+    };
+    for change in changes {
+        match change {
+            Change::Memory { key, old, new } => {
+                let detail = format!("{} \u{2192} {}", size(*old), size(*new));
+                fmt.text_summary("memory:", &format!("{} {}", delta(*old, *new), key), &detail, Level::Ok);
+            }
+            Change::Storage { mount, old, new } => {
+                let detail = format!("{} \u{2192} {} on {}", size(*old), size(*new), mount);
+                fmt.text_summary("storage:", &format!("{} used", delta(*old, *new)), &detail, Level::Ok);
+            }
+            Change::Added { name, pid } => {
+                fmt.text_proc_warn(format!("{} [{}]", name, pid), String::from("new service"))
+            }
+            Change::Removed { name, pid } => {
+                fmt.text_proc_err(format!("{} [{}]", name, pid), String::from("service gone"))
+            }
+            Change::Restarted { name, pid, old } => {
+                fmt.text_proc_warn(format!("{} [{}]", name, pid), format!("restarted (was {})", old))
+            }
+            Change::Rss { name, pid, old, new } => {
+                let detail = format!("{} rss ({} \u{2192} {})", delta(*old, *new), size(*old), size(*new));
+                fmt.text_proc_ok(format!("{} [{}]", name, pid), detail)
+            }
+            Change::Cputime { name, pid, secs } => {
+                fmt.text_proc_ok(format!("{} [{}]", name, pid), format!("cpu +{}", elapsed(*secs)))
+            }
         }
-        let total = disk.total_space();
-        let avail = disk.available_space();
-        let availpct = 100_f64 * avail as f64 / total as f64;
-        let info = format!("{} ({:.1}%) free", format_size(avail, sizefmt), availpct);
-        let detail = [
-            format!("{} used", format_size(total - avail, sizefmt)),
-            format!("{} total", format_size(total, sizefmt)),
-            format!("on {}", disk.mount_point().display()),
-        ];
-        fmt.text_summary("storage:", &info, &detail.join(" \u{2219} "));
-        fmt.json_open("", false, false);
-        fmt.json_key_val("total", total);
-        fmt.json_key_val("used", total - avail);
-        fmt.json_key_val("free", avail);
-        fmt.json_key_str("dev", disk.name().to_str().unwrap_or(""));
-        fmt.json_key_str("mount", disk.mount_point().display());
-        fmt.json_close(false);
     }
-    fmt.json_close(true);
 }
 
-fn procsummary(
-    sys: &System,
-    fmt: &mut fmt::Format,
-    conf: &conf::Config,
-    all: bool,
-    sort: Option<SortBy>,
-    limit: Option<usize>,
-) -> i32 {
-    let now = time::SystemTime::now();
-    let epoch = now.duration_since(time::UNIX_EPOCH).unwrap_or_default().as_secs();
-    let procs = proc::ProcessMap::new(sys);
-    let mut found = vec![];
-    let mut items = vec![];
-    let mut errors = 0;
+fn parse_format(name: &str) -> Option<fmt::Format> {
+    match name {
+        "text" => Some(fmt::Format::text()),
+        "json" => Some(fmt::Format::json()),
+        "prometheus" => Some(fmt::Format::prometheus()),
+        "nagios" => Some(fmt::Format::nagios()),
+        _ => None,
+    }
+}
 
-    // Configured services
-    for (title, pid, err) in conf.service_matches(&procs) {
-        if pid == 0 {
-            items.push(ProcItem {
-                pid: 0,
-                name: title.to_string(),
-                cpu: 0,
-                rss: 0,
-                uptime: 0,
-                warn: false,
-                msg: err,
-            });
-            errors += 1;
-        } else if !found.contains(&pid) {
-            found.push(pid);
-            if let Some(proc) = sys.process(Pid::from_u32(pid)) {
-                let uptime = epoch - proc.start_time();
-                let (cputime, rssbytes) = procs.stat(&pid);
-                items.push(ProcItem {
-                    pid,
-                    name: title.to_string(),
-                    cpu: cputime,
-                    rss: rssbytes,
-                    uptime,
-                    warn: !err.is_empty(),
-                    msg: err,
-                });
-            }
-        }
+fn alerts(fmt: &mut fmt::Format, alerts: &Alerts) {
+    for (level, msg) in alerts.iter() {
+        fmt.text_alert(*level, msg);
+        fmt.nagios_alert(*level, msg);
     }
+}
 
-    // Other services
-    let mut services = procs.services();
-    services.sort();
-    for pid in services {
-        if all
-            && !found.contains(&pid)
-            && let Some(proc) = sys.process(Pid::from_u32(pid))
-        {
-            if proc.exe().is_none() && proc.memory() == 0 {
-                // Let's ignore kernel threads
-                continue;
-            }
-            let uptime = epoch - proc.start_time();
-            let (cputime, rssbytes) = procs.stat(&pid);
-            items.push(ProcItem {
-                pid,
-                name: proc.name().to_str().unwrap_or_default().to_string(),
-                cpu: cputime,
-                rss: rssbytes,
-                uptime,
-                warn: true,
-                msg: String::from(""),
-            });
+fn cpusummary(fmt: &mut fmt::Format, summary: &Summary) {
+    let [one, five, fifteen] = summary.loadavg;
+    let load = format!("{:.2}, {:.2}, {:.2}", one, five, fifteen);
+    let detail = [
+        format!("up {}", elapsed(summary.uptime)),
+        format!("{} processes", summary.processes),
+        format!("{} cores", summary.cores),
+    ];
+    fmt.text_summary("loadavg:", &load, &detail.join(" \u{2219} "), summary.alerts.level());
+    alerts(fmt, &summary.alerts);
+    fmt.prom_metric("cores", "gauge", "Number of physical CPU cores.", &[], summary.cores);
+    fmt.prom_metric("uptime_seconds", "counter", "Machine uptime in seconds.", &[], summary.uptime);
+    for (period, value) in [("1m", one), ("5m", five), ("15m", fifteen)] {
+        fmt.prom_metric("load_average", "gauge", "Load average.", &[("period", period)], value);
+    }
+    fmt.prom_metric("processes", "gauge", "Number of processes.", &[], summary.processes);
+    fmt.nagios_perf("load1", one, "", None);
+    fmt.nagios_perf("load5", five, "", None);
+    fmt.nagios_perf("load15", fifteen, "", None);
+    if let Some(cpu) = &summary.cpu {
+        let detail: Vec<String> = cpu.cores.iter().map(|pct| format!("{:.0}%", pct)).collect();
+        fmt.text_summary("cpu:", &format!("{:.1}% used", cpu.usage), &detail.join(" "), Level::Ok);
+        let help = "CPU usage percent over the sample interval.";
+        fmt.prom_metric("cpu_usage_percent", "gauge", help, &[("core", "all")], cpu.usage);
+        for (idx, pct) in cpu.cores.iter().enumerate() {
+            fmt.prom_metric("cpu_usage_percent", "gauge", help, &[("core", &idx.to_string())], pct);
         }
+        fmt.nagios_perf("cpu", cpu.usage, "%", Some(100));
     }
+}
 
-    // Sort & print
-    if let Some(s) = sort {
-        items.sort_by(|a, b| match s {
-            SortBy::Cpu => b.cpu.cmp(&a.cpu),
-            SortBy::Rss => b.rss.cmp(&a.rss),
-            SortBy::Uptime => b.uptime.cmp(&a.uptime),
-        });
-    }
-    if let Some(n) = limit
-        && n < items.len()
-    {
-        items.truncate(n);
+fn memsummary(fmt: &mut fmt::Format, mem: &Memory) {
+    let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
+    let freepct = 100_f64 * mem.free as f64 / mem.total as f64;
+    let info = format!("{} ({:.1}%) free", format_size(mem.free, sizefmt), freepct);
+    let mut detail = vec![
+        format!("{} rss", format_size(mem.rss, sizefmt)),
+        format!("{} cache", format_size(mem.cache, sizefmt)),
+        format!("{} total", format_size(mem.total, sizefmt)),
+    ];
+    if mem.swap > 0 {
+        detail.insert(2, format!("{} swap", format_size(mem.swap, sizefmt)));
     }
+    fmt.text_summary("memory:", &info, &detail.join(" \u{2219} "), mem.alerts.level());
+    alerts(fmt, &mem.alerts);
+    fmt.prom_metric("memory_total_bytes", "gauge", "Total memory in bytes.", &[], mem.total);
+    fmt.prom_metric("memory_free_bytes", "gauge", "Free memory in bytes.", &[], mem.free);
+    fmt.prom_metric("memory_rss_bytes", "gauge", "Used (resident) memory in bytes.", &[], mem.rss);
+    fmt.prom_metric("memory_cache_bytes", "gauge", "Cache memory in bytes.", &[], mem.cache);
+    fmt.prom_metric("memory_swap_bytes", "gauge", "Used swap in bytes.", &[], mem.swap);
+    fmt.nagios_perf("mem_free", mem.free, "B", Some(mem.total));
+    fmt.nagios_perf("mem_rss", mem.rss, "B", Some(mem.total));
+    fmt.nagios_perf("mem_cache", mem.cache, "B", Some(mem.total));
+    fmt.nagios_perf("swap", mem.swap, "B", None);
+}
 
-    // Print
-    fmt.json_open("services", true, true);
-    for item in items {
-        printitem(fmt, item);
+fn storagesummary(fmt: &mut fmt::Format, storage: &[Storage]) {
+    let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
+    for disk in storage {
+        let freepct = 100_f64 * disk.free as f64 / disk.total as f64;
+        let info = format!("{} ({:.1}%) free", format_size(disk.free, sizefmt), freepct);
+        let detail = [
+            format!("{} used", format_size(disk.used, sizefmt)),
+            format!("{} total", format_size(disk.total, sizefmt)),
+            format!("on {}", disk.mount),
+        ];
+        fmt.text_summary("storage:", &info, &detail.join(" \u{2219} "), disk.alerts.level());
+        alerts(fmt, &disk.alerts);
+        let labels = [("mount", disk.mount.as_str()), ("device", disk.dev.as_str())];
+        fmt.prom_metric("storage_total_bytes", "gauge", "Total storage in bytes.", &labels, disk.total);
+        fmt.prom_metric("storage_used_bytes", "gauge", "Used storage in bytes.", &labels, disk.used);
+        fmt.prom_metric("storage_free_bytes", "gauge", "Free storage in bytes.", &labels, disk.free);
+        fmt.nagios_perf(&format!("storage {}", disk.mount), disk.used, "B", Some(disk.total));
     }
-    fmt.json_close(true);
-    errors
 }
 
-fn printitem(fmt: &mut fmt::Format, item: ProcItem) {
+fn printitem(fmt: &mut fmt::Format, item: &ServiceItem) {
     let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
     let label = format!("{} [{}]", item.name, item.pid);
-    let detail = [
-        format!("cpu {}", elapsed(item.cpu)),
-        format!("up {}", elapsed(item.uptime)),
-        format!("{} rss", format_size(item.rss, sizefmt)),
-    ];
-    if item.pid == 0 {
-        fmt.text_proc_err(label, item.msg.clone());
-    } else if item.warn {
-        fmt.text_proc_warn(label, detail.join(" \u{2219} "));
-        if !item.msg.is_empty() {
-            fmt.text_proc_more("Warning:", item.msg.clone());
+    match &item.stats {
+        None => fmt.text_proc_err(label, item.alerts.message(Level::Critical)),
+        Some(stats) => {
+            let mut detail = vec![
+                format!("cpu {}", elapsed(stats.cputime)),
+                format!("up {}", elapsed(stats.uptime)),
+                format!("{} rss", format_size(stats.rss, sizefmt)),
+            ];
+            match (stats.io.read_rate, stats.io.written_rate) {
+                (Some(r), Some(w)) => {
+                    detail.push(format!("{}/s read", format_size(r, sizefmt)));
+                    detail.push(format!("{}/s written", format_size(w, sizefmt)));
+                }
+                _ if stats.io.read + stats.io.written > 0 => {
+                    detail.push(format!("{} read", format_size(stats.io.read, sizefmt)));
+                    detail.push(format!("{} written", format_size(stats.io.written, sizefmt)));
+                }
+                _ => {}
+            }
+            if let Some(smaps) = &stats.smaps {
+                detail.push(format!("{} pss", format_size(smaps.pss, sizefmt)));
+                detail.push(format!("{} uss", format_size(smaps.uss, sizefmt)));
+                if smaps.swap > 0 {
+                    detail.push(format!("{} swap", format_size(smaps.swap, sizefmt)));
+                }
+            }
+            if let Some(pct) = stats.cpu {
+                detail.insert(0, format!("{:.1}% cpu", pct));
+            }
+            if let Some(c) = &item.container {
+                detail.push(c.image.clone());
+            }
+            if let Some(n) = item.instances
+                && n != 1
+            {
+                detail.push(format!("{} instances", n));
+            }
+            if !item.listen.is_empty() {
+                let listen: Vec<String> = item.listen.iter().map(|l| l.to_string()).collect();
+                detail.push(format!("listen {}", listen.join(" ")));
+            }
+            if let Some(cg) = &item.cgroup {
+                detail.push(format!("{} cgroup", format_size(cg.memory, sizefmt)));
+            }
+            let detail = detail.join(" \u{2219} ");
+            match item.alerts.level() {
+                Level::Ok => fmt.text_proc_ok(label, detail),
+                Level::Warning => fmt.text_proc_warn(label, detail),
+                Level::Critical => fmt.text_proc_err(label, detail),
+            }
+            for (level, msg) in item.service_alerts().iter() {
+                fmt.text_alert(*level, msg);
+            }
+            for child in &item.children {
+                let detail = [
+                    format!("cpu {}", elapsed(child.cputime)),
+                    format!("{} rss", format_size(child.rss, sizefmt)),
+                    child
+                        .cmd
+                        .chars()
+                        .map(|c| if c.is_control() { ' ' } else { c })
+                        .take(60)
+                        .collect(),
+                ];
+                let label = format!("{} [{}]", child.name, child.pid);
+                fmt.text_proc_child(child.depth, label, detail.join(" \u{2219} "));
+            }
         }
-    } else {
-        fmt.text_proc_ok(label, detail.join(" \u{2219} "));
     }
-    fmt.json_open("", false, false);
-    fmt.json_key_val("pid", item.pid);
-    fmt.json_key_str("name", item.name);
-    if item.pid == 0 {
-        fmt.json_key_str("error", item.msg.clone());
-    } else {
-        fmt.json_key_val("cputime", item.cpu);
-        fmt.json_key_val("uptime", item.uptime);
-        fmt.json_key_val("rss", item.rss);
-    }
-    if item.warn {
-        if item.msg.is_empty() {
-            fmt.json_key_str("warning", "not listed in config");
-        } else {
-            fmt.json_key_str("warning", item.msg);
+}
+
+fn metricitem(fmt: &mut fmt::Format, item: &ServiceItem) {
+    let name = item.name.as_str();
+    let up = if item.stats.is_some() { 1 } else { 0 };
+    fmt.prom_metric("service_up", "gauge", "Service running status (1 = up).", &[("name", name)], up);
+    let level = item.alerts.level();
+    let msg = item.service_alerts().message(level);
+    fmt.nagios_service(name, level == Level::Critical, &msg);
+    if let Some(stats) = &item.stats {
+        fmt.nagios_perf(&format!("{} cpu", name), stats.cputime, "c", None);
+        fmt.nagios_perf(&format!("{} rss", name), stats.rss, "B", None);
+        let pid = item.pid.to_string();
+        let labels = [("name", name), ("pid", pid.as_str())];
+        let help = "Service CPU time in seconds (incl. children).";
+        fmt.prom_metric("service_cputime_seconds", "counter", help, &labels, stats.cputime);
+        if let Some(pct) = stats.cpu {
+            let help = "Service CPU usage percent over the sample interval (incl. children).";
+            fmt.prom_metric("service_cpu_percent", "gauge", help, &labels, pct);
+        }
+        let help = "Service uptime in seconds.";
+        fmt.prom_metric("service_uptime_seconds", "counter", help, &labels, stats.uptime);
+        let help = "Service resident memory in bytes (incl. children).";
+        fmt.prom_metric("service_rss_bytes", "gauge", help, &labels, stats.rss);
+        let help = "Service disk bytes read (incl. children).";
+        fmt.prom_metric("service_io_read_bytes", "counter", help, &labels, stats.io.read);
+        let help = "Service disk bytes written (incl. children).";
+        fmt.prom_metric("service_io_written_bytes", "counter", help, &labels, stats.io.written);
+        if let Some(smaps) = &stats.smaps {
+            let help = "Service proportional set size in bytes (incl. children).";
+            fmt.prom_metric("service_pss_bytes", "gauge", help, &labels, smaps.pss);
+            let help = "Service unique set size in bytes (incl. children).";
+            fmt.prom_metric("service_uss_bytes", "gauge", help, &labels, smaps.uss);
+            let help = "Service swap usage in bytes (incl. children).";
+            fmt.prom_metric("service_swap_bytes", "gauge", help, &labels, smaps.swap);
+        }
+        if let Some(cg) = &item.cgroup {
+            let help = "Service cgroup memory usage in bytes.";
+            fmt.prom_metric("service_cgroup_memory_bytes", "gauge", help, &labels, cg.memory);
+            let help = "Service cgroup CPU time in seconds.";
+            fmt.prom_metric("service_cgroup_cputime_seconds", "counter", help, &labels, cg.cputime);
         }
     }
-    fmt.json_close(false);
 }
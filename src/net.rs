@@ -0,0 +1,133 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{read_dir, read_link, read_to_string};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+pub const ROOT: &str = "/proc";
+
+const TCP_LISTEN: &str = "0A";
+const UNIX_ACCEPTCON: u32 = 0x10000;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Listen {
+    pub proto: &'static str,
+    pub addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addr = if self.addr.contains(':') {
+            format!("[{}]", self.addr)
+        } else {
+            self.addr.clone()
+        };
+        match (self.proto, self.port) {
+            ("tcp" | "tcp6", Some(port)) => write!(f, "{}:{}", addr, port),
+            (proto, Some(port)) => write!(f, "{}/{}:{}", proto.trim_end_matches('6'), addr, port),
+            (_, None) => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+pub fn listening(root: &Path) -> HashMap<u64, Listen> {
+    let mut res = HashMap::new();
+    for proto in ["tcp", "tcp6", "udp", "udp6"] {
+        let data = read_to_string(root.join("net").join(proto)).unwrap_or_default();
+        for line in data.lines().skip(1) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 10 {
+                continue;
+            }
+            let listen = if proto.starts_with("tcp") {
+                cols[3] == TCP_LISTEN
+            } else {
+                cols[2].split(':').nth(1) == Some("0000")
+            };
+            if let (true, Some((addr, port)), Ok(inode)) = (listen, parse_addr(cols[1]), cols[9].parse()) {
+                res.insert(inode, Listen { proto, addr, port: Some(port) });
+            }
+        }
+    }
+    let data = read_to_string(root.join("net/unix")).unwrap_or_default();
+    for line in data.lines().skip(1) {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        let flags = cols.get(3).and_then(|s| u32::from_str_radix(s, 16).ok()).unwrap_or(0);
+        if let (true, Some(path), Some(Ok(inode))) =
+            (flags & UNIX_ACCEPTCON != 0, cols.get(7), cols.get(6).map(|s| s.parse()))
+        {
+            res.insert(inode, Listen { proto: "unix", addr: path.to_string(), port: None });
+        }
+    }
+    res
+}
+
+fn parse_addr(value: &str) -> Option<(String, u16)> {
+    let (addr, port) = value.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let bytes: Vec<u8> = (0..addr.len() / 8)
+        .map(|i| u32::from_str_radix(addr.get(i * 8..i * 8 + 8)?, 16).ok().map(u32::to_ne_bytes))
+        .collect::<Option<Vec<_>>>()?
+        .concat();
+    let addr = match bytes.len() {
+        4 => Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string(),
+        16 => Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string(),
+        _ => return None,
+    };
+    Some((addr, port))
+}
+
+pub fn socket_inodes(root: &Path, pid: u32) -> Option<Vec<u64>> {
+    let entries = read_dir(root.join(pid.to_string()).join("fd")).ok()?;
+    let inodes = entries
+        .flatten()
+        .filter_map(|e| read_link(e.path()).ok())
+        .filter_map(|link| {
+            let link = link.to_string_lossy();
+            link.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+        })
+        .collect();
+    Some(inodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proc")
+    }
+
+    #[test]
+    fn test_parse_addr() {
+        assert_eq!(parse_addr("0100007F:1538"), Some((String::from("127.0.0.1"), 5432)));
+        let ipv6 = "00000000000000000000000001000000:1F90";
+        assert_eq!(parse_addr(ipv6), Some((String::from("::1"), 8080)));
+        assert_eq!(parse_addr("nonsense"), None);
+    }
+
+    #[test]
+    fn test_listening() {
+        let found = listening(&root());
+        let mut inodes: Vec<u64> = found.keys().copied().collect();
+        inodes.sort();
+        assert_eq!(inodes, [1001, 1002, 2001, 2002, 3001, 4001, 4003]);
+        assert_eq!(found[&1001].to_string(), "0.0.0.0:80");
+        assert_eq!(found[&2001].to_string(), "[::]:443");
+        assert_eq!(found[&3001].to_string(), "udp/0.0.0.0:53");
+        assert_eq!(found[&4001].to_string(), "/run/php/php-fpm.sock");
+    }
+
+    #[test]
+    fn test_socket_inodes() {
+        let mut inodes = socket_inodes(&root(), 100).unwrap();
+        inodes.sort();
+        assert_eq!(inodes, [1001, 2001, 4002]);
+        assert_eq!(socket_inodes(&root(), 101), Some(vec![4001]));
+        assert_eq!(socket_inodes(&root(), 999), None);
+    }
+}
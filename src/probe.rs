@@ -0,0 +1,163 @@
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Probes {
+    pub tcp: Option<String>,
+    pub socket: Option<String>,
+    pub http: Option<String>,
+    pub http_status: Option<u16>,
+    #[serde(default, deserialize_with = "crate::conf::de_secs")]
+    pub timeout: Option<u64>,
+}
+
+impl Probes {
+    pub fn check(&self) -> Vec<String> {
+        let timeout = Duration::from_secs(self.timeout.unwrap_or(5).max(1));
+        let mut errors = vec![];
+        if let Some(addr) = &self.tcp
+            && let Err(err) = tcp(addr, timeout)
+        {
+            errors.push(format!("tcp {}: {}", addr, err));
+        }
+        if let Some(path) = &self.socket
+            && let Err(err) = socket(path, timeout)
+        {
+            errors.push(format!("socket {}: {}", path, err));
+        }
+        if let Some(url) = &self.http
+            && let Err(err) = http(url, self.http_status, timeout)
+        {
+            errors.push(format!("http {}: {}", url, err));
+        }
+        errors
+    }
+}
+
+fn connect(addr: &str, timeout: Duration) -> Result<TcpStream, Error> {
+    let mut last = Error::new(ErrorKind::NotFound, "address not found");
+    for sockaddr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&sockaddr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+pub fn tcp(addr: &str, timeout: Duration) -> Result<(), Error> {
+    connect(addr, timeout).map(|_| ())
+}
+
+pub fn socket(path: &str, timeout: Duration) -> Result<(), Error> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(timeout))
+}
+
+pub fn http(url: &str, expect: Option<u16>, timeout: Duration) -> Result<(), Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidInput, msg.to_string());
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("only http:// URLs are supported"))?;
+    let (host, path) = rest.find('/').map(|i| rest.split_at(i)).unwrap_or((rest, "/"));
+    let addr = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let mut stream = connect(&addr, timeout)?;
+    let req = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\nUser-Agent: upstate\r\nConnection: close\r\n\r\n");
+    stream.write_all(req.as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid HTTP response"))?;
+    let ok = match expect {
+        Some(code) => status == code,
+        None => (200..400).contains(&status),
+    };
+    if ok {
+        Ok(())
+    } else {
+        let expected = expect.map(|c| c.to_string()).unwrap_or(String::from("2xx or 3xx"));
+        Err(Error::other(format!("HTTP status {} (expected {})", status, expected)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn http_server(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut req = vec![];
+            let mut buf = [0u8; 1024];
+            while !req.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(n) if n > 0 => req.extend_from_slice(&buf[..n]),
+                    _ => break,
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}/health", addr)
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        assert!(tcp(&addr, TIMEOUT).is_ok());
+        drop(listener);
+        assert!(tcp(&addr, TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_socket() {
+        let path = std::env::temp_dir().join(format!("upstate-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        assert!(socket(path.to_str().unwrap(), TIMEOUT).is_ok());
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+        assert!(socket(path.to_str().unwrap(), TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_http() {
+        let url = http_server("HTTP/1.0 200 OK\r\n\r\nok");
+        assert!(http(&url, None, TIMEOUT).is_ok());
+        let url = http_server("HTTP/1.1 503 Service Unavailable\r\n\r\n");
+        let err = http(&url, None, TIMEOUT).unwrap_err();
+        assert_eq!(err.to_string(), "HTTP status 503 (expected 2xx or 3xx)");
+        let url = http_server("HTTP/1.1 204 No Content\r\n\r\n");
+        assert!(http(&url, Some(200), TIMEOUT).is_err());
+        assert!(http("https://localhost/", None, TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_probes_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let probes = Probes { tcp: Some(addr), ..Default::default() };
+        assert!(probes.check().is_empty());
+        let probes = Probes { socket: Some(String::from("/nonexistent.sock")), ..Default::default() };
+        assert_eq!(probes.check().len(), 1);
+        assert!(probes.check()[0].starts_with("socket /nonexistent.sock: "));
+    }
+}
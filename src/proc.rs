@@ -1,12 +1,67 @@
 use regex::RegexBuilder;
+use serde::Serialize;
+use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use sysinfo::System;
+use std::fs::{read_link, read_to_string};
+use std::path::Path;
+use std::sync::OnceLock;
+use sysinfo::{System, Users};
 
+use crate::container::Containers;
+
+pub const ROOT: &str = "/proc";
+
+#[derive(Default)]
 pub struct ProcessInfo {
-    cmd: String,
-    cpu: u64,
-    rss: u64,
+    pub cmd: String,
+    pub exe: String,
+    pub cwd: String,
+    pub comm: String,
+    pub uid: Option<u32>,
+    pub cpu: u64,
+    pub rss: u64,
+    pub pct: f32,
+    pub io: IoStats,
+    pub io_delta: IoStats,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IoStats {
+    pub read: u64,
+    pub written: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Smaps {
+    pub pss: u64,
+    pub uss: u64,
+    pub swap: u64,
+}
+
+impl Smaps {
+    pub fn parse(data: &str) -> Smaps {
+        let mut res = Smaps::default();
+        for line in data.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let kb: u64 = value.trim().trim_end_matches("kB").trim().parse().unwrap_or(0);
+            match key {
+                "Pss" => res.pss += kb * 1024,
+                "Private_Clean" | "Private_Dirty" => res.uss += kb * 1024,
+                "Swap" => res.swap += kb * 1024,
+                _ => {}
+            }
+        }
+        res
+    }
+
+    fn add(&mut self, other: &Smaps) {
+        self.pss += other.pss;
+        self.uss += other.uss;
+        self.swap += other.swap;
+    }
 }
 
 pub struct ProcessMap {
@@ -14,30 +69,48 @@ pub struct ProcessMap {
     parents: HashMap<u32, u32>,
     children: HashMap<u32, Vec<u32>>,
     info: HashMap<u32, ProcessInfo>,
+    containers: OnceCell<Containers>,
 }
 
 impl ProcessMap {
     pub fn new(sys: &System) -> ProcessMap {
-        let mut roots = vec![];
-        let mut parents = HashMap::new();
-        let mut children = HashMap::new();
-        let mut info = HashMap::new();
+        let mut procs = vec![];
         for (pid, proc) in sys.processes() {
             if proc.thread_kind().is_none() {
-                children.entry(pid.as_u32()).or_insert(vec![]);
                 let cmd = proc.cmd().join(OsStr::new(" ")).to_string_lossy().into_owned();
+                let exe = proc.exe().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+                let cwd = proc.cwd().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+                let comm = proc.name().to_string_lossy().into_owned();
+                let uid = proc.user_id().map(|uid| **uid);
                 let rss = proc.memory();
                 let cpu = proc.accumulated_cpu_time() / 1000;
-                info.insert(pid.as_u32(), ProcessInfo { cmd, cpu, rss });
-                if let Some(ppid) = proc.parent() {
-                    parents.insert(pid.as_u32(), ppid.as_u32());
-                    children.entry(ppid.as_u32()).or_insert(vec![]).push(pid.as_u32());
-                } else {
-                    roots.push(pid.as_u32());
-                }
+                let pct = proc.cpu_usage();
+                let disk = proc.disk_usage();
+                let io = IoStats { read: disk.total_read_bytes, written: disk.total_written_bytes };
+                let io_delta = IoStats { read: disk.read_bytes, written: disk.written_bytes };
+                let item = ProcessInfo { cmd, exe, cwd, comm, uid, cpu, rss, pct, io, io_delta };
+                procs.push((pid.as_u32(), proc.parent().map(|p| p.as_u32()), item));
+            }
+        }
+        ProcessMap::from_procs(procs)
+    }
+
+    fn from_procs(procs: Vec<(u32, Option<u32>, ProcessInfo)>) -> ProcessMap {
+        let mut roots = vec![];
+        let mut parents = HashMap::new();
+        let mut children = HashMap::new();
+        let mut info = HashMap::new();
+        for (pid, ppid, item) in procs {
+            children.entry(pid).or_insert(vec![]);
+            info.insert(pid, item);
+            if let Some(ppid) = ppid {
+                parents.insert(pid, ppid);
+                children.entry(ppid).or_insert(vec![]).push(pid);
+            } else {
+                roots.push(pid);
             }
         }
-        ProcessMap { roots, parents, children, info }
+        ProcessMap { roots, parents, children, info, containers: OnceCell::new() }
     }
 
     fn is_service(&self, pid: &u32) -> bool {
@@ -70,30 +143,375 @@ impl ProcessMap {
         self.info.contains_key(pid).then(|| self.as_service(pid))
     }
 
+    pub fn services_by_group(&self, pids: &[u32]) -> Vec<u32> {
+        pids.iter()
+            .filter(|pid| self.info.contains_key(pid))
+            .filter(|pid| self.parents.get(pid).is_none_or(|ppid| !pids.contains(ppid)))
+            .copied()
+            .collect()
+    }
+
+    pub fn services_by_container(&self, ids: &[&str]) -> Vec<u32> {
+        let mut pids: Vec<u32> = self
+            .info
+            .keys()
+            .filter(|pid| {
+                read_cgroup(Path::new(ROOT), **pid)
+                    .and_then(|path| scope_id(&path))
+                    .is_some_and(|id| ids.contains(&id.as_str()))
+            })
+            .copied()
+            .collect();
+        pids.sort();
+        self.container_services(&pids)
+    }
+
+    fn container_services(&self, pids: &[u32]) -> Vec<u32> {
+        // The container init is a child of containerd-shim or conmon
+        let mut res: Vec<u32> = self.services_by_group(pids).iter().map(|pid| self.as_service(pid)).collect();
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    pub fn containers(&self) -> &Containers {
+        self.containers.get_or_init(Containers::load)
+    }
+
     pub fn services_by_cmd(&self, cmd: &str) -> Vec<u32> {
         let re = RegexBuilder::new(cmd).case_insensitive(true).build();
-        let is_match = |s: &String| s.contains(cmd) || re.as_ref().is_ok_and(|r| r.is_match(s));
-        self.info
+        self.services_by(|info| info.cmd.contains(cmd) || re.as_ref().is_ok_and(|r| r.is_match(&info.cmd)))
+    }
+
+    pub fn services_by(&self, is_match: impl Fn(&ProcessInfo) -> bool) -> Vec<u32> {
+        let own = std::process::id();
+        let mut pids: Vec<u32> = self
+            .info
             .iter()
-            .filter(|(_, v)| is_match(&v.cmd))
-            .map(|(k, _)| self.as_service(k))
-            .collect()
+            .filter(|(pid, info)| **pid != own && is_match(info))
+            .map(|(pid, _)| self.as_service(pid))
+            .collect();
+        pids.sort();
+        pids.dedup();
+        pids
+    }
+
+    pub fn info(&self, pid: &u32) -> Option<&ProcessInfo> {
+        self.info.get(pid)
     }
 
-    pub fn stat(&self, pid: &u32) -> (u64, u64) {
+    pub fn descendants(&self, pid: &u32) -> Vec<(u32, usize)> {
+        let mut res = vec![];
+        let mut stack = vec![(*pid, 0)];
+        while let Some((pid, depth)) = stack.pop() {
+            if depth > 0 {
+                res.push((pid, depth));
+            }
+            if let Some(child_pids) = self.children.get(&pid) {
+                let mut child_pids = child_pids.clone();
+                child_pids.sort_by(|a, b| b.cmp(a));
+                stack.extend(child_pids.into_iter().map(|cid| (cid, depth + 1)));
+            }
+        }
+        res
+    }
+
+    pub fn service_label(&self, pid: u32, comm: &str) -> String {
+        let root = Path::new(ROOT);
+        let own = read_cgroup(root, pid);
+        let child = self
+            .children
+            .get(&pid)
+            .and_then(|c| c.first())
+            .and_then(|c| read_cgroup(root, *c));
+        cgroup_label(own.as_deref(), child.as_deref()).unwrap_or_else(|| comm.to_string())
+    }
+
+    pub fn stat(&self, pid: &u32) -> (u64, u64, f32) {
         let mut cpu = 0;
         let mut rss = 0;
+        let mut pct = 0.0;
         if let Some(info) = self.info.get(pid) {
             cpu += info.cpu;
             rss += info.rss;
+            pct += info.pct;
             if let Some(child_pids) = self.children.get(pid) {
                 for cid in child_pids {
-                    let (c, r) = self.stat(cid);
+                    let (c, r, p) = self.stat(cid);
                     cpu += c;
                     rss += r;
+                    pct += p;
                 }
             }
         }
-        (cpu, rss)
+        (cpu, rss, pct)
+    }
+
+    pub fn io(&self, pid: &u32) -> (IoStats, IoStats) {
+        let mut total = IoStats::default();
+        let mut delta = IoStats::default();
+        if let Some(info) = self.info.get(pid) {
+            total = info.io;
+            delta = info.io_delta;
+            for cid in self.children.get(pid).into_iter().flatten() {
+                let (t, d) = self.io(cid);
+                total.read += t.read;
+                total.written += t.written;
+                delta.read += d.read;
+                delta.written += d.written;
+            }
+        }
+        (total, delta)
+    }
+
+    pub fn smaps(&self, root: &Path, pid: &u32) -> Option<Smaps> {
+        let data = read_to_string(root.join(pid.to_string()).join("smaps_rollup")).ok()?;
+        let mut res = Smaps::parse(&data);
+        for cid in self.children.get(pid).into_iter().flatten() {
+            if let Some(child) = self.smaps(root, cid) {
+                res.add(&child);
+            }
+        }
+        Some(res)
+    }
+}
+
+pub fn uid_by_name(name: &str) -> Option<u32> {
+    static USERS: OnceLock<Users> = OnceLock::new();
+    let users = USERS.get_or_init(Users::new_with_refreshed_list);
+    users.list().iter().find(|u| u.name() == name).map(|u| **u.id())
+}
+
+const DELETED: &str = " (deleted)";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Deleted {
+    pub exe: Option<String>,
+    pub libs: Vec<String>,
+}
+
+pub fn deleted_files(root: &Path, pid: u32) -> Deleted {
+    let dir = root.join(pid.to_string());
+    let mut res = Deleted::default();
+    if let Ok(exe) = read_link(dir.join("exe")) {
+        res.exe = exe.to_string_lossy().strip_suffix(DELETED).map(String::from);
+    }
+    for line in read_to_string(dir.join("maps")).unwrap_or_default().lines() {
+        let cols: Vec<&str> = line.splitn(6, char::is_whitespace).collect();
+        let (perms, path) = (cols.get(1).unwrap_or(&""), cols.get(5).map_or("", |s| s.trim()));
+        if perms.contains('x')
+            && let Some(path) = path.strip_suffix(DELETED)
+            && path.starts_with('/')
+            && !path.starts_with("/memfd:")
+            && !path.starts_with("/dev/")
+            && res.exe.as_deref() != Some(path)
+        {
+            res.libs.push(path.to_string());
+        }
+    }
+    res.libs.sort();
+    res.libs.dedup();
+    res
+}
+
+const CONTAINER_SCOPES: [(&str, &str); 6] = [
+    ("docker-", "docker"),
+    ("libpod-conmon-", "podman"),
+    ("libpod-", "podman"),
+    ("cri-containerd-", "k8s"),
+    ("crio-", "k8s"),
+    ("nerdctl-", "ctr"),
+];
+
+fn read_cgroup(root: &Path, pid: u32) -> Option<String> {
+    let data = read_to_string(root.join(pid.to_string()).join("cgroup")).ok()?;
+    data.lines().find_map(|line| line.strip_prefix("0::")).map(String::from)
+}
+
+fn cgroup_label(own: Option<&str>, child: Option<&str>) -> Option<String> {
+    own.and_then(|path| scope_label(path, true))
+        .or_else(|| child.and_then(|path| scope_label(path, false)))
+        .or_else(|| own.and_then(unit_label))
+}
+
+fn scope_label(path: &str, machines: bool) -> Option<String> {
+    for part in path.split('/') {
+        if let Some(label) = container_label(part) {
+            return Some(label);
+        }
+        if machines
+            && let Some(name) = part.strip_prefix("machine-").and_then(|s| s.strip_suffix(".scope"))
+            && !name.is_empty()
+        {
+            return Some(format!("vm/{}", name));
+        }
+    }
+    None
+}
+
+fn unit_label(path: &str) -> Option<String> {
+    path.split('/')
+        .rev()
+        .find_map(|part| part.strip_suffix(".service"))
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+}
+
+fn container_scope(part: &str) -> Option<(&'static str, &str)> {
+    let scope = part.strip_suffix(".scope")?;
+    let (prefix, kind) = CONTAINER_SCOPES.iter().find(|(prefix, _)| scope.starts_with(prefix))?;
+    let id = &scope[prefix.len()..];
+    (id.len() >= 64 && id.chars().all(|c| c.is_ascii_hexdigit())).then_some((*kind, id))
+}
+
+fn container_label(part: &str) -> Option<String> {
+    container_scope(part).map(|(kind, id)| format!("{}/{}", kind, &id[..12]))
+}
+
+fn scope_id(path: &str) -> Option<String> {
+    path.split('/').find_map(container_scope).map(|(_, id)| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const CONMON: u32 = 300;
+    const CRIO: u32 = 301;
+    const DOCKER: u32 = 302;
+    const GETTY: u32 = 303;
+    const K8S: u32 = 304;
+    const MACHINE: u32 = 305;
+    const NERDCTL: u32 = 306;
+    const PODMAN: u32 = 307;
+    const TOPLEVEL: u32 = 308;
+    const SESSION: u32 = 309;
+    const SHIM: u32 = 310;
+    const SSHD: u32 = 311;
+    const UDEVD: u32 = 312;
+    const V1: u32 = 313;
+
+    fn root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proc")
+    }
+
+    fn fixture(pid: u32) -> Option<String> {
+        read_cgroup(&root(), pid)
+    }
+
+    fn label(pid: u32) -> Option<String> {
+        cgroup_label(fixture(pid).as_deref(), None)
+    }
+
+    #[test]
+    fn test_cgroup_service() {
+        assert_eq!(label(SSHD).as_deref(), Some("sshd"));
+        assert_eq!(label(UDEVD).as_deref(), Some("systemd-udevd"));
+        assert_eq!(label(GETTY).as_deref(), Some("getty@tty1"));
+        assert_eq!(label(SHIM).as_deref(), Some("containerd"));
+        assert_eq!(label(SESSION), None);
+        assert_eq!(label(TOPLEVEL), None);
+        assert_eq!(fixture(V1), None);
+        assert_eq!(read_cgroup(&root(), 999), None);
+    }
+
+    #[test]
+    fn test_cgroup_container() {
+        for (pid, expected) in [
+            (DOCKER, "docker/9cec00b45bcf"),
+            (PODMAN, "podman/9cec00b45bcf"),
+            (CONMON, "podman/9cec00b45bcf"),
+            (K8S, "k8s/9cec00b45bcf"),
+            (CRIO, "k8s/9cec00b45bcf"),
+            (NERDCTL, "ctr/9cec00b45bcf"),
+        ] {
+            assert_eq!(label(pid).as_deref(), Some(expected), "{}", pid);
+        }
+        assert_eq!(container_label("docker-abc.scope"), None);
+    }
+
+    #[test]
+    fn test_cgroup_scope_id() {
+        let id = "9cec00b45bcf7f1d3c0a4b4a6cd1ab0a8d6e2f3a1b2c3d4e5f60718293a4b5c6";
+        assert_eq!(fixture(DOCKER).and_then(|p| scope_id(&p)).as_deref(), Some(id));
+        assert_eq!(fixture(PODMAN).and_then(|p| scope_id(&p)).as_deref(), Some(id));
+        assert_eq!(fixture(SSHD).and_then(|p| scope_id(&p)), None);
+    }
+
+    #[test]
+    fn test_cgroup_machine() {
+        assert_eq!(label(MACHINE).as_deref(), Some("vm/dev--sandbox"));
+    }
+
+    #[test]
+    fn test_smaps() {
+        let smaps = Smaps::parse(&read_to_string(root().join("100/smaps_rollup")).unwrap());
+        assert_eq!(smaps, Smaps { pss: 6144 << 10, uss: 4096 << 10, swap: 512 << 10 });
+        let proc = |pid: u32, ppid: Option<u32>| (pid, ppid, ProcessInfo::default());
+        let procs = ProcessMap::from_procs(vec![proc(1, None), proc(100, Some(1)), proc(101, Some(100))]);
+        let total = procs.smaps(&root(), &100).unwrap();
+        assert_eq!(total, Smaps { pss: 7168 << 10, uss: 4608 << 10, swap: 512 << 10 });
+        assert_eq!(
+            procs.smaps(&root(), &101),
+            Some(Smaps { pss: 1024 << 10, uss: 512 << 10, swap: 0 })
+        );
+        assert_eq!(procs.smaps(&root(), &1), None);
+    }
+
+    #[test]
+    fn test_deleted_files() {
+        let root = root();
+        let found = deleted_files(&root, 200);
+        assert_eq!(found.exe.as_deref(), Some("/usr/sbin/nginx"));
+        assert_eq!(found.libs, ["/usr/lib/x86_64-linux-gnu/libssl.so.3"]);
+        assert_eq!(deleted_files(&root, 101), Deleted::default());
+        assert_eq!(deleted_files(&root, 999), Deleted::default());
+    }
+
+    #[test]
+    fn test_descendants() {
+        let proc = |pid: u32, ppid: Option<u32>, comm: &str| {
+            (pid, ppid, ProcessInfo { comm: String::from(comm), ..Default::default() })
+        };
+        let procs = ProcessMap::from_procs(vec![
+            proc(1, None, "init"),
+            proc(10, Some(1), "sshd"),
+            proc(12, Some(11), "sleep"),
+            proc(11, Some(10), "sh"),
+            proc(14, Some(10), "sshd"),
+            proc(13, Some(11), "sleep"),
+        ]);
+        assert_eq!(procs.services(), [10]);
+        assert_eq!(procs.descendants(&10), [(11, 1), (12, 2), (13, 2), (14, 1)]);
+        assert!(procs.descendants(&14).is_empty());
+        assert!(procs.descendants(&99).is_empty());
+        assert_eq!(procs.info(&11).map(|i| i.comm.as_str()), Some("sh"));
+    }
+
+    #[test]
+    fn test_container_services() {
+        let proc = |pid: u32, ppid: Option<u32>| (pid, ppid, ProcessInfo::default());
+        let procs = ProcessMap::from_procs(vec![
+            proc(1, None),
+            proc(20, Some(1)),
+            proc(21, Some(20)),
+            proc(22, Some(21)),
+            proc(30, Some(1)),
+            proc(31, Some(30)),
+        ]);
+        assert_eq!(procs.services(), [20, 30]);
+        assert_eq!(procs.container_services(&[21, 22]), [20]);
+        assert_eq!(procs.container_services(&[21, 22, 31]), [20, 30]);
+        assert!(procs.container_services(&[99]).is_empty());
+    }
+
+    #[test]
+    fn test_cgroup_child() {
+        let shim = fixture(SHIM);
+        let label = |child: u32| cgroup_label(shim.as_deref(), fixture(child).as_deref());
+        assert_eq!(label(DOCKER).as_deref(), Some("docker/9cec00b45bcf"));
+        assert_eq!(label(MACHINE).as_deref(), Some("containerd"));
+        assert_eq!(label(SSHD).as_deref(), Some("containerd"));
     }
 }
@@ -0,0 +1,66 @@
+use humansize::{BINARY, FormatSizeOptions, format_size};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::System;
+
+use crate::{error, fmt};
+use upstate::conf::{Config, Level};
+use upstate::history::{self, Recorder};
+use upstate::report::{Options, Report, elapsed, refresh};
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub fn record(config: &Config, opts: &Options, recorder: &Recorder, interval: Duration) {
+    let mut sys = System::new();
+    loop {
+        let start = Instant::now();
+        refresh(&mut sys, opts.sample);
+        let report = Report::new(&sys, config, opts);
+        if let Err(err) = recorder.append(&report, now()) {
+            error(format!("failed to record to {}: {}", recorder.dir.display(), err));
+        }
+        thread::sleep(interval.saturating_sub(start.elapsed()));
+    }
+}
+
+pub struct Query {
+    pub service: Option<String>,
+    pub field: String,
+    pub since: Duration,
+    pub sparkline: bool,
+}
+
+pub fn history(fmt: &mut fmt::Format, dir: &Path, query: &Query) -> i32 {
+    let (service, field) = (query.service.as_deref(), query.field.as_str());
+    let since = now().saturating_sub(query.since.as_secs());
+    let series = match history::query(dir, service, field, since) {
+        Ok(series) => series,
+        Err(err) => {
+            error(format!("failed to read history in {}: {}", dir.display(), err));
+            return 1;
+        }
+    };
+    fmt.json_report(&series);
+    let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
+    let value = |v: f64| match field.rsplit('.').next().unwrap_or(field) {
+        "rss" | "memory" | "total" | "free" | "used" | "cache" | "swap" | "read" | "written" => {
+            format_size(v as u64, sizefmt)
+        }
+        "cputime" | "uptime" => elapsed(v as u64),
+        _ => format!("{}", v),
+    };
+    let label = format!("{}{}", service.map(|s| format!("{} ", s)).unwrap_or_default(), field);
+    let values: Vec<f64> = series.iter().map(|(_, v)| *v).collect();
+    let detail = match (values.first(), values.last()) {
+        (Some(first), Some(last)) => format!("{} \u{2192} {}", value(*first), value(*last)),
+        _ => String::from("no data"),
+    };
+    fmt.text_summary(&label, &history::sparkline(&values), &detail, Level::Ok);
+    for (time, v) in series.iter().filter(|_| !query.sparkline) {
+        fmt.text_summary("", &history::datetime(*time), &value(*v), Level::Ok);
+    }
+    0
+}
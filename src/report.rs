@@ -0,0 +1,827 @@
+use humansize::{BINARY, FormatSizeOptions, format_size};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::{DiskKind, Disks, MINIMUM_CPU_UPDATE_INTERVAL, Pid, System};
+
+use crate::cgroup::{self, CgroupStats};
+use crate::conf::{Config, Level, Limits, ServiceDisplay, SortBy, Thresholds};
+use crate::container::Container;
+use crate::net::{self, Listen};
+use crate::proc::{self, ProcessMap, Smaps};
+use crate::state;
+
+#[derive(Default)]
+pub struct Options {
+    pub summary: bool,
+    pub services: ServiceDisplay,
+    pub sort: Option<SortBy>,
+    pub limit: Option<usize>,
+    pub sample: Option<Duration>,
+    pub state: Option<PathBuf>,
+    pub tree: bool,
+    pub smaps: bool,
+}
+
+impl Options {
+    pub fn new(config: &Config) -> Options {
+        Options {
+            summary: config.display_summary != crate::conf::SummaryDisplay::None,
+            services: config.display_services,
+            sort: config.sort,
+            limit: config.limit,
+            sample: config.sample,
+            state: config.state.clone(),
+            tree: false,
+            smaps: config.smaps,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Alerts(Vec<(Level, String)>);
+
+impl Alerts {
+    pub fn push(&mut self, level: Level, msg: String) {
+        if level != Level::Ok && !msg.is_empty() {
+            self.0.push((level, msg));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Level, String)> {
+        self.0.iter()
+    }
+
+    pub fn level(&self) -> Level {
+        self.0.iter().map(|(level, _)| *level).max().unwrap_or(Level::Ok)
+    }
+
+    pub fn errors(&self) -> usize {
+        self.0.iter().filter(|(level, _)| *level == Level::Critical).count()
+    }
+
+    pub fn message(&self, level: Level) -> String {
+        let msgs: Vec<&str> = self.0.iter().filter(|(l, _)| *l == level).map(|(_, m)| m.as_str()).collect();
+        msgs.join(", ")
+    }
+}
+
+impl Serialize for Alerts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (key, level) in [("error", Level::Critical), ("warning", Level::Warning)] {
+            let msg = self.message(level);
+            if !msg.is_empty() {
+                map.serialize_entry(key, &msg)?;
+            }
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub summary: Option<Summary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub services: Option<Vec<ServiceItem>>,
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub cores: usize,
+    pub uptime: u64,
+    pub loadavg: [f64; 3],
+    pub processes: usize,
+    #[serde(flatten)]
+    pub alerts: Alerts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuUsage>,
+    pub memory: Memory,
+    pub storage: Vec<Storage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CpuUsage {
+    pub usage: f32,
+    pub cores: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Memory {
+    pub total: u64,
+    pub free: u64,
+    pub rss: u64,
+    pub cache: u64,
+    pub swap: u64,
+    #[serde(flatten)]
+    pub alerts: Alerts,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Storage {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    pub dev: String,
+    pub mount: String,
+    #[serde(flatten)]
+    pub alerts: Alerts,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceItem {
+    pub pid: u32,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instances: Option<usize>,
+    #[serde(flatten)]
+    pub stats: Option<ProcStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<CgroupStats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<ChildItem>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub listen: Vec<Listen>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+    #[serde(skip)]
+    pub configured: bool,
+    #[serde(flatten)]
+    pub alerts: Alerts,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChildItem {
+    pub pid: u32,
+    pub depth: usize,
+    pub name: String,
+    pub cmd: String,
+    pub cputime: u64,
+    pub rss: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProcStats {
+    pub cputime: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<f32>,
+    pub uptime: u64,
+    pub rss: u64,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub smaps: Option<Smaps>,
+    pub io: IoUsage,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct IoUsage {
+    pub read: u64,
+    pub written: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_rate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub written_rate: Option<u64>,
+}
+
+impl Report {
+    pub fn new(sys: &System, config: &Config, opts: &Options) -> Report {
+        let mut summary = opts.summary.then(|| summary(sys, &config.thresholds, opts.sample.is_some()));
+        let mut warnings = vec![];
+        let services = (opts.services != ServiceDisplay::None).then(|| {
+            let mut items = services(sys, config, opts);
+            if let Some(path) = &opts.state {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let complete = opts.services == ServiceDisplay::All;
+                if let Err(err) = state::update(path, &mut items, now, complete) {
+                    let msg = format!("state file {}: {}", path.display(), err);
+                    match &mut summary {
+                        Some(summary) => summary.alerts.push(Level::Warning, msg),
+                        None => warnings.push(msg),
+                    }
+                }
+            }
+            sort(&mut items, opts);
+            items
+        });
+        Report { summary, services, warnings }
+    }
+
+    pub fn errors(&self) -> usize {
+        let mut errors = 0;
+        if let Some(summary) = &self.summary {
+            errors += summary.alerts.errors() + summary.memory.alerts.errors();
+            errors += summary.storage.iter().map(|s| s.alerts.errors()).sum::<usize>();
+        }
+        if let Some(services) = &self.services {
+            errors += services.iter().map(|s| s.alerts.errors()).sum::<usize>();
+        }
+        errors
+    }
+}
+
+impl ServiceItem {
+    pub fn cpu(&self) -> f32 {
+        self.stats.as_ref().and_then(|s| s.cpu).unwrap_or(0.0)
+    }
+
+    pub fn cputime(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| s.cputime)
+    }
+
+    pub fn rss(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| s.rss)
+    }
+
+    pub fn io(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| match (s.io.read_rate, s.io.written_rate) {
+            (Some(r), Some(w)) => r + w,
+            _ => s.io.read + s.io.written,
+        })
+    }
+
+    pub fn smaps(&self) -> Smaps {
+        self.stats.as_ref().and_then(|s| s.smaps.clone()).unwrap_or_default()
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |s| s.uptime)
+    }
+
+    /// Alerts without the leading "not listed in config" warning of other services.
+    pub fn service_alerts(&self) -> Alerts {
+        let skip = if self.configured { 0 } else { 1 };
+        Alerts(self.alerts.0.iter().skip(skip).cloned().collect())
+    }
+}
+
+pub fn refresh(sys: &mut System, sample: Option<Duration>) {
+    sys.refresh_all();
+    if let Some(duration) = sample {
+        thread::sleep(duration.max(MINIMUM_CPU_UPDATE_INTERVAL));
+        sys.refresh_all();
+    }
+}
+
+pub fn elapsed(secs: u64) -> String {
+    let mins = secs / 60;
+    let hours = mins / 60;
+    let days = hours / 24;
+    if days > 0 {
+        format!("{} days", days)
+    } else {
+        format!("{:02}:{:02}:{:02}", hours, mins % 60, secs % 60)
+    }
+}
+
+fn finite(value: f32) -> f32 {
+    if value.is_finite() { value } else { 0.0 }
+}
+
+fn summary(sys: &System, limits: &Thresholds, sampled: bool) -> Summary {
+    let cores = System::physical_core_count().unwrap_or(1);
+    let load = System::load_average();
+    let percpu = load.one / sys.cpus().len().max(1) as f64;
+    let mut alerts = Alerts::default();
+    let level = limits.load.above(percpu);
+    alerts.push(level, format!("load {:.2} per CPU above {}", percpu, limits.load.limit(level)));
+    let cpu = sampled.then(|| CpuUsage {
+        usage: finite(sys.global_cpu_usage()),
+        cores: sys.cpus().iter().map(|c| finite(c.cpu_usage())).collect(),
+    });
+    Summary {
+        cores,
+        uptime: System::uptime(),
+        loadavg: [load.one, load.five, load.fifteen],
+        processes: sys.processes().len(),
+        alerts,
+        cpu,
+        memory: memory(sys, limits),
+        storage: storage(limits),
+    }
+}
+
+fn memory(sys: &System, limits: &Thresholds) -> Memory {
+    let total = sys.total_memory();
+    let free = sys.free_memory();
+    let freepct = 100_f64 * free as f64 / total as f64;
+    let swap = sys.used_swap();
+    let mut alerts = Alerts::default();
+    let level = limits.memory_free.below(freepct);
+    alerts.push(
+        level,
+        format!("free memory {:.1}% below {}%", freepct, limits.memory_free.limit(level)),
+    );
+    if sys.total_swap() > 0 {
+        let swappct = 100_f64 * swap as f64 / sys.total_swap() as f64;
+        let level = limits.swap_used.above(swappct);
+        alerts.push(
+            level,
+            format!("used swap {:.1}% above {}%", swappct, limits.swap_used.limit(level)),
+        );
+    }
+    Memory {
+        total,
+        free,
+        rss: sys.used_memory(),
+        cache: sys.available_memory().saturating_sub(free),
+        swap,
+        alerts,
+    }
+}
+
+fn storage(limits: &Thresholds) -> Vec<Storage> {
+    let mut devices = HashSet::new();
+    let mut res = vec![];
+    let disks = Disks::new_with_refreshed_list();
+    for disk in disks.list() {
+        if let DiskKind::Unknown(_) = disk.kind() {
+            continue;
+        } else if !devices.insert(disk.name()) {
+            continue;
+        }
+        let total = disk.total_space();
+        let avail = disk.available_space();
+        let availpct = 100_f64 * avail as f64 / total as f64;
+        let mount = disk.mount_point().to_string_lossy().into_owned();
+        let limit = limits.storage(&mount);
+        let mut alerts = Alerts::default();
+        let level = limit.below(availpct);
+        let msg = format!("free storage {:.1}% below {}% on {}", availpct, limit.limit(level), mount);
+        alerts.push(level, msg);
+        res.push(Storage {
+            total,
+            used: total - avail,
+            free: avail,
+            dev: disk.name().to_string_lossy().into_owned(),
+            mount,
+            alerts,
+        });
+    }
+    res
+}
+
+fn services(sys: &System, conf: &Config, opts: &Options) -> Vec<ServiceItem> {
+    let now = SystemTime::now();
+    let epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let procs = ProcessMap::new(sys);
+    let smaps = opts.smaps || matches!(opts.sort, Some(SortBy::Pss | SortBy::Uss | SortBy::Swap));
+    let secs = opts.sample.map(|d| d.max(MINIMUM_CPU_UPDATE_INTERVAL).as_secs_f64());
+    let stats = |pid: u32, start: u64| {
+        let (cputime, rss, pct) = procs.stat(&pid);
+        let smaps = smaps.then(|| procs.smaps(Path::new(proc::ROOT), &pid)).flatten();
+        let (total, delta) = procs.io(&pid);
+        let rate = |bytes: u64| secs.map(|s| (bytes as f64 / s) as u64);
+        let io = IoUsage {
+            read: total.read,
+            written: total.written,
+            read_rate: rate(delta.read),
+            written_rate: rate(delta.written),
+        };
+        ProcStats {
+            cputime,
+            cpu: opts.sample.map(|_| finite(pct)),
+            uptime: epoch - start,
+            rss,
+            smaps,
+            io,
+        }
+    };
+    let children = |pid: u32| {
+        let pids = if opts.tree { procs.descendants(&pid) } else { vec![] };
+        pids.into_iter()
+            .filter_map(|(pid, depth)| {
+                let info = procs.info(&pid)?;
+                let (name, cmd) = (info.comm.clone(), info.cmd.clone());
+                Some(ChildItem { pid, depth, name, cmd, cputime: info.cpu, rss: info.rss })
+            })
+            .collect()
+    };
+    let sockets = net::listening(Path::new(net::ROOT));
+    let listen = |pid: u32| {
+        let mut pids = vec![pid];
+        pids.extend(procs.descendants(&pid).into_iter().map(|(pid, _)| pid));
+        let mut known = true;
+        let mut res: Vec<Listen> = vec![];
+        for pid in pids {
+            match net::socket_inodes(Path::new(net::ROOT), pid) {
+                Some(inodes) => res.extend(inodes.iter().filter_map(|inode| sockets.get(inode).cloned())),
+                None => known = false,
+            }
+        }
+        res.sort();
+        res.dedup();
+        (res, known)
+    };
+    let deleted = |pid: u32| {
+        let mut pids = vec![pid];
+        pids.extend(procs.descendants(&pid).into_iter().map(|(pid, _)| pid));
+        let (mut files, mut exe, mut libs) = (vec![], false, false);
+        for pid in pids {
+            let found = proc::deleted_files(Path::new(proc::ROOT), pid);
+            exe |= found.exe.is_some();
+            libs |= !found.libs.is_empty();
+            files.extend(found.exe.into_iter().chain(found.libs));
+        }
+        files.sort();
+        files.dedup();
+        (files, restartcheck(exe, libs))
+    };
+    let root = Path::new(cgroup::ROOT);
+    let mut found = vec![];
+    let mut items = vec![];
+
+    // Configured services
+    for svc in &conf.services {
+        let mut probes = None;
+        let missing = |msg: String| {
+            let mut alerts = Alerts::default();
+            alerts.push(Level::Critical, msg);
+            ServiceItem {
+                pid: 0,
+                name: svc.name.clone(),
+                container: None,
+                instances: None,
+                stats: None,
+                cgroup: None,
+                children: vec![],
+                listen: vec![],
+                deleted: vec![],
+                configured: true,
+                alerts,
+            }
+        };
+        let matches = svc.matches(&procs);
+        let mut fresh: Vec<u32> = matches
+            .iter()
+            .map(|(_, pid, _)| *pid)
+            .filter(|pid| *pid != 0 && !found.contains(pid) && sys.process(Pid::from_u32(*pid)).is_some())
+            .collect();
+        fresh.sort();
+        fresh.dedup();
+        let count = fresh.len();
+        let mut instances = Some(svc.check_instances(count));
+        let first = items.len();
+        let claimed = matches.iter().any(|(_, pid, _)| *pid != 0);
+        let mut known = true;
+        for (svc, pid, err) in matches {
+            if pid == 0 {
+                items.push(missing(err));
+            } else if !found.contains(&pid) {
+                found.push(pid);
+                if let Some(proc) = sys.process(Pid::from_u32(pid)) {
+                    let stats = stats(pid, proc.start_time());
+                    let mut alerts = Alerts::default();
+                    for msg in probes.get_or_insert_with(|| svc.probes.check()).iter() {
+                        alerts.push(Level::Critical, msg.clone());
+                    }
+                    alerts.push(Level::Warning, err);
+                    if let Some((level, msg)) = instances.take() {
+                        alerts.push(level, msg);
+                    }
+                    for msg in limitcheck(&svc.limits, &stats) {
+                        alerts.push(Level::Warning, msg);
+                    }
+                    let (files, restart) = deleted(pid);
+                    alerts.push(Level::Warning, restart);
+                    let (sockets, readable) = listen(pid);
+                    known &= readable;
+                    let name = svc.name.clone();
+                    items.push(ServiceItem {
+                        pid,
+                        name,
+                        container: None,
+                        instances: Some(count),
+                        stats: Some(stats),
+                        cgroup: svc.unit.as_ref().and_then(|u| cgroup::stats(&cgroup::unit_path(root, u))),
+                        children: children(pid),
+                        listen: sockets,
+                        deleted: files,
+                        configured: true,
+                        alerts,
+                    });
+                }
+            }
+        }
+        if items.len() == first && claimed && svc.min_instances() > 0 {
+            items.push(missing(String::from("processes already matched by another service")));
+        }
+        for msg in listencheck(&svc.listen, &items[first..], known) {
+            items[first].alerts.push(Level::Warning, msg);
+        }
+    }
+
+    // Other services
+    let mut services = procs.services();
+    services.sort();
+    for pid in services {
+        if opts.services == ServiceDisplay::All
+            && !found.contains(&pid)
+            && let Some(proc) = sys.process(Pid::from_u32(pid))
+        {
+            if proc.exe().is_none() && proc.memory() == 0 {
+                // Let's ignore kernel threads
+                continue;
+            }
+            let mut alerts = Alerts::default();
+            alerts.push(Level::Warning, String::from("not listed in config"));
+            let (files, restart) = deleted(pid);
+            alerts.push(Level::Warning, restart);
+            let mut name = procs.service_label(pid, &proc.name().to_string_lossy());
+            let container = procs.containers().resolve(&name).cloned();
+            if let Some(c) = &container
+                && let Some((kind, _)) = name.split_once('/')
+            {
+                name = format!("{}/{}", kind, c.name);
+            }
+            items.push(ServiceItem {
+                pid,
+                name,
+                container,
+                instances: None,
+                stats: Some(stats(pid, proc.start_time())),
+                cgroup: None,
+                children: children(pid),
+                listen: listen(pid).0,
+                deleted: files,
+                configured: false,
+                alerts,
+            });
+        }
+    }
+
+    items
+}
+
+fn sort(items: &mut Vec<ServiceItem>, opts: &Options) {
+    if let Some(s) = opts.sort {
+        items.sort_by(|a, b| match s {
+            SortBy::Cpu if opts.sample.is_some() => b.cpu().total_cmp(&a.cpu()),
+            SortBy::Cpu => b.cputime().cmp(&a.cputime()),
+            SortBy::Rss => b.rss().cmp(&a.rss()),
+            SortBy::Uptime => b.uptime().cmp(&a.uptime()),
+            SortBy::Io => b.io().cmp(&a.io()),
+            SortBy::Pss => b.smaps().pss.cmp(&a.smaps().pss),
+            SortBy::Uss => b.smaps().uss.cmp(&a.smaps().uss),
+            SortBy::Swap => b.smaps().swap.cmp(&a.smaps().swap),
+        });
+    }
+    if let Some(n) = opts.limit {
+        items.truncate(n);
+    }
+}
+
+fn limitcheck(limits: &Limits, stats: &ProcStats) -> Vec<String> {
+    let sizefmt = FormatSizeOptions::from(BINARY).decimal_places(1);
+    let mut msgs = vec![];
+    if let Some(max) = limits.max_rss
+        && stats.rss > max
+    {
+        let (rss, max) = (format_size(stats.rss, sizefmt), format_size(max, sizefmt));
+        msgs.push(format!("rss {} above max {}", rss, max));
+    }
+    if let Some(max) = limits.max_cputime
+        && stats.cputime > max
+    {
+        msgs.push(format!("cpu time {} above max {}", elapsed(stats.cputime), elapsed(max)));
+    }
+    if let Some(max) = limits.max_cpu_percent
+        && let Some(pct) = stats.cpu
+        && pct > max
+    {
+        msgs.push(format!("cpu {:.1}% above max {:.1}%", pct, max));
+    }
+    if let Some(min) = limits.min_uptime
+        && stats.uptime < min
+    {
+        msgs.push(format!("uptime {} below min {}", elapsed(stats.uptime), elapsed(min)));
+    }
+    if let Some(max) = limits.max_uptime
+        && stats.uptime > max
+    {
+        msgs.push(format!("uptime {} above max {}", elapsed(stats.uptime), elapsed(max)));
+    }
+    msgs
+}
+
+/// Missing ports are only reported if all socket fds of `items` were readable.
+fn listencheck(ports: &[u16], items: &[ServiceItem], known: bool) -> Vec<String> {
+    let running: Vec<&ServiceItem> = items.iter().filter(|i| i.stats.is_some()).collect();
+    if running.is_empty() || !known {
+        return vec![];
+    }
+    let bound: Vec<u16> = running.iter().flat_map(|i| i.listen.iter().filter_map(|l| l.port)).collect();
+    ports
+        .iter()
+        .filter(|p| !bound.contains(p))
+        .map(|p| format!("port {} not listening", p))
+        .collect()
+}
+
+fn restartcheck(exe: bool, libs: bool) -> String {
+    match (exe, libs) {
+        (false, false) => String::new(),
+        (true, false) => String::from("needs restart: binary updated"),
+        (false, true) => String::from("needs restart: library updated"),
+        (true, true) => String::from("needs restart: binary and library updated"),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::conf::Service;
+
+    fn options(services: ServiceDisplay) -> Options {
+        Options { services, ..Default::default() }
+    }
+
+    pub(crate) fn item(name: &str, pid: u32, stats: Option<ProcStats>) -> ServiceItem {
+        ServiceItem {
+            pid,
+            name: String::from(name),
+            container: None,
+            instances: None,
+            stats,
+            cgroup: None,
+            children: vec![],
+            listen: vec![],
+            deleted: vec![],
+            configured: true,
+            alerts: Alerts::default(),
+        }
+    }
+
+    #[test]
+    fn test_alerts() {
+        let mut alerts = Alerts::default();
+        assert_eq!(alerts.level(), Level::Ok);
+        alerts.push(Level::Ok, String::from("ignored"));
+        alerts.push(Level::Warning, String::from(""));
+        alerts.push(Level::Warning, String::from("w1"));
+        alerts.push(Level::Critical, String::from("e1"));
+        alerts.push(Level::Warning, String::from("w2"));
+        assert_eq!(alerts.iter().count(), 3);
+        assert_eq!(alerts.level(), Level::Critical);
+        assert_eq!(alerts.errors(), 1);
+        assert_eq!(alerts.message(Level::Warning), "w1, w2");
+    }
+
+    #[test]
+    fn test_missing_service() {
+        let mut config = Config::empty();
+        config.services.push(Service {
+            name: String::from("missing"),
+            required: true,
+            command: Some(String::from("^no-such-process$")),
+            ..Default::default()
+        });
+        let sys = System::new_all();
+        let report = Report::new(&sys, &config, &options(ServiceDisplay::Required));
+        assert!(report.summary.is_none());
+        let services = report.services.as_ref().unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].pid, 0);
+        assert!(services[0].stats.is_none());
+        assert_eq!(services[0].alerts.level(), Level::Critical);
+        assert_eq!(report.errors(), 1);
+    }
+
+    #[test]
+    fn test_service_alerts() {
+        let mut other = ServiceItem { configured: false, ..item("sys/foo", 42, Some(ProcStats::default())) };
+        other.alerts.push(Level::Warning, String::from("not listed in config"));
+        other.alerts.push(Level::Warning, String::from("new since last check"));
+        assert_eq!(other.service_alerts().message(Level::Warning), "new since last check");
+        let mut configured = item("web", 43, Some(ProcStats::default()));
+        configured
+            .alerts
+            .push(Level::Warning, String::from("multiple matching processes"));
+        assert_eq!(configured.service_alerts(), configured.alerts);
+    }
+
+    #[test]
+    fn test_state_warning() {
+        let opts = Options {
+            services: ServiceDisplay::Required,
+            state: Some(PathBuf::from("/nonexistent/upstate/state.json")),
+            ..Default::default()
+        };
+        let report = Report::new(&System::new(), &Config::empty(), &opts);
+        assert!(report.summary.is_none());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("state file /nonexistent/upstate/state.json: "));
+    }
+
+    #[test]
+    fn test_claimed_service() {
+        let pidfile = std::env::temp_dir().join(format!("upstate-claimed-{}.pid", std::process::id()));
+        std::fs::write(&pidfile, "1\n").unwrap();
+        let mut config = Config::empty();
+        for name in ["first", "second"] {
+            config.services.push(Service {
+                name: String::from(name),
+                required: true,
+                pidfile: Some(pidfile.to_string_lossy().into_owned()),
+                ..Default::default()
+            });
+        }
+        let sys = System::new_all();
+        let report = Report::new(&sys, &config, &options(ServiceDisplay::Required));
+        std::fs::remove_file(&pidfile).unwrap();
+        let services = report.services.as_ref().unwrap();
+        assert_eq!(services.len(), 2);
+        assert_eq!((services[0].pid, services[0].instances), (1, Some(1)));
+        assert_eq!(services[1].pid, 0);
+        let msg = services[1].alerts.message(Level::Critical);
+        assert_eq!(msg, "processes already matched by another service");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut alerts = Alerts::default();
+        alerts.push(Level::Warning, String::from("pid file \"/run/a\\b.pid\" invalid"));
+        let report = Report {
+            summary: None,
+            warnings: vec![],
+            services: Some(vec![
+                ServiceItem {
+                    alerts,
+                    ..item(
+                        "quote\"back\\slash\u{1}",
+                        42,
+                        Some(ProcStats { cputime: 1, cpu: Some(f32::NAN), uptime: 2, rss: 3, ..Default::default() }),
+                    )
+                },
+                item("missing", 0, None),
+            ]),
+        };
+        let json = serde_json::to_string(&report).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let svc = &value["services"][0];
+        assert_eq!(svc["name"], "quote\"back\\slash\u{1}");
+        assert_eq!(svc["warning"], "pid file \"/run/a\\b.pid\" invalid");
+        assert!(svc["cpu"].is_null());
+        assert_eq!(svc["rss"], 3);
+        assert_eq!(value["services"][1].as_object().unwrap().len(), 2);
+        assert!(value.get("memory").is_none());
+    }
+
+    #[test]
+    fn test_io() {
+        let io = IoUsage { read: 100, written: 200, ..Default::default() };
+        let mut item = item("db", 42, Some(ProcStats { io, ..Default::default() }));
+        assert_eq!(item.io(), 300);
+        if let Some(stats) = &mut item.stats {
+            stats.io.read_rate = Some(5);
+            stats.io.written_rate = Some(10);
+        }
+        assert_eq!(item.io(), 15);
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["io"]["written_rate"], 10);
+    }
+
+    #[test]
+    fn test_listencheck() {
+        let listen = |proto, port| Listen { proto, addr: String::from("0.0.0.0"), port: Some(port) };
+        let web = ServiceItem {
+            listen: vec![listen("tcp", 80), listen("udp", 53)],
+            ..item("web", 42, Some(ProcStats::default()))
+        };
+        let worker = ServiceItem { listen: vec![listen("tcp", 443)], ..item("web", 43, Some(ProcStats::default())) };
+        let items = [web, worker];
+        assert!(listencheck(&[80, 443, 53], &items, true).is_empty());
+        assert_eq!(listencheck(&[80, 8080], &items, true), ["port 8080 not listening"]);
+        assert!(listencheck(&[80, 8080], &items, false).is_empty());
+        assert!(listencheck(&[80], &[item("web", 0, None)], true).is_empty());
+    }
+
+    #[test]
+    fn test_restartcheck() {
+        assert_eq!(restartcheck(false, false), "");
+        assert_eq!(restartcheck(true, false), "needs restart: binary updated");
+        assert_eq!(restartcheck(false, true), "needs restart: library updated");
+        assert_eq!(restartcheck(true, true), "needs restart: binary and library updated");
+    }
+
+    #[test]
+    fn test_limitcheck() {
+        let limits = Limits { max_rss: Some(1024), min_uptime: Some(60), ..Default::default() };
+        let stats = ProcStats {
+            cputime: 10,
+            cpu: None,
+            uptime: 30,
+            rss: 2048,
+            smaps: None,
+            io: IoUsage::default(),
+        };
+        let msgs = limitcheck(&limits, &stats);
+        assert_eq!(msgs, ["rss 2 KiB above max 1 KiB", "uptime 00:00:30 below min 00:01:00"]);
+    }
+}
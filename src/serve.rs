@@ -0,0 +1,136 @@
+use std::io::{BufRead, BufReader, Error, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use sysinfo::System;
+
+use crate::{fmt, report};
+use upstate::conf::{Config, ServiceDisplay};
+use upstate::report::{Options, refresh};
+
+pub fn serve(addr: &str, config: &Config, opts: &Options) -> Result<(), Error> {
+    let listener = TcpListener::bind(addr)?;
+    let mut sys = System::new_all();
+    for stream in listener.incoming().flatten() {
+        if let Err(err) = handle(stream, &mut sys, config, opts) {
+            crate::warning(format!("failed to handle HTTP request: {}", err));
+        }
+    }
+    Ok(())
+}
+
+fn handle(stream: TcpStream, sys: &mut System, config: &Config, opts: &Options) -> Result<(), Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+    let (status, mime, body) = match (method, path) {
+        ("GET" | "HEAD", "/metrics") => {
+            refresh(sys, opts.sample);
+            let mut fmt = fmt::Format::prometheus();
+            report(sys, &mut fmt, config, opts);
+            ("200 OK", "text/plain; version=0.0.4", fmt.finish())
+        }
+        ("GET" | "HEAD", "/report.json") => {
+            refresh(sys, opts.sample);
+            let mut fmt = fmt::Format::json();
+            report(sys, &mut fmt, config, opts);
+            ("200 OK", "application/json", fmt.finish())
+        }
+        ("GET" | "HEAD", "/health") => {
+            refresh(sys, None);
+            let mut fmt = fmt::Format::nagios();
+            let health = Options { services: ServiceDisplay::Required, ..Default::default() };
+            let errors = report(sys, &mut fmt, config, &health);
+            let status = if errors > 0 { "503 Service Unavailable" } else { "200 OK" };
+            (status, "text/plain", fmt.finish())
+        }
+        ("GET" | "HEAD", _) => ("404 Not Found", "text/plain", String::from("not found\n")),
+        _ => ("405 Method Not Allowed", "text/plain", String::from("method not allowed\n")),
+    };
+    let mut out = &stream;
+    write!(out, "HTTP/1.1 {}\r\n", status)?;
+    write!(out, "Content-Type: {}\r\n", mime)?;
+    write!(out, "Content-Length: {}\r\n", body.len())?;
+    write!(out, "Connection: close\r\n\r\n")?;
+    if method != "HEAD" {
+        out.write_all(body.as_bytes())?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::thread;
+    use upstate::conf::Service;
+
+    fn request(path: &str, config: &Config) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let req = format!("GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path);
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(req.as_bytes()).unwrap();
+            let mut res = String::new();
+            stream.read_to_string(&mut res).unwrap();
+            res
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let opts = Options { summary: true, limit: Some(3), ..Default::default() };
+        handle(stream, &mut System::new(), config, &opts).unwrap();
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn test_health() {
+        let res = request("/health", &Config::empty());
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(res.contains("\r\n\r\nUPSTATE OK - "), "{}", res);
+    }
+
+    #[test]
+    fn test_health_missing() {
+        let mut config = Config::empty();
+        config.services.push(Service {
+            name: String::from("missing"),
+            required: true,
+            command: Some(String::from("^no-such-process$")),
+            ..Default::default()
+        });
+        let res = request("/health", &config);
+        assert!(res.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", res);
+        assert!(res.contains("\r\n\r\nUPSTATE CRITICAL - missing: "), "{}", res);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let res = request("/metrics", &Config::empty());
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(res.contains("\n# TYPE upstate_memory_total_bytes gauge\n"), "{}", res);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let res = request("/report.json?pretty", &Config::empty());
+        assert!(res.contains("Content-Type: application/json\r\n"), "{}", res);
+        assert!(res.contains("\r\n\r\n{\"cores\":"), "{}", res);
+        let body = res.split("\r\n\r\n").nth(1).unwrap();
+        let value: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert!(value["loadavg"].as_array().is_some_and(|a| a.len() == 3), "{}", body);
+        assert!(value["memory"]["total"].is_u64(), "{}", body);
+    }
+
+    #[test]
+    fn test_not_found() {
+        let res = request("/missing", &Config::empty());
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", res);
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, rename, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::conf::Level;
+use crate::report::ServiceItem;
+
+const HOUR: u64 = 3600;
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct State {
+    pub time: u64,
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceState>,
+    #[serde(default)]
+    pub others: Option<BTreeSet<String>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ServiceState {
+    pub pid: u32,
+    pub start: u64,
+    #[serde(default)]
+    pub restarts: Vec<u64>,
+}
+
+impl State {
+    pub fn load(path: &Path) -> Result<State, Error> {
+        match read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(State::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string(self).map_err(Error::other)?;
+        let tmp = path.with_extension("tmp");
+        write(&tmp, json + "\n")?;
+        rename(&tmp, path)
+    }
+
+    pub fn update(&mut self, items: &mut [ServiceItem], now: u64, complete: bool) {
+        let started = |item: &ServiceItem| item.stats.as_ref().map(|s| (item.pid, now.saturating_sub(s.uptime)));
+        let mut others = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        for idx in 0..items.len() {
+            let Some((pid, start)) = started(&items[idx]) else {
+                continue;
+            };
+            let name = items[idx].name.clone();
+            if !items[idx].configured {
+                if let Some(prev) = &self.others
+                    && !prev.contains(&name)
+                {
+                    items[idx].alerts.push(Level::Warning, String::from("new since last check"));
+                }
+                others.insert(name);
+                continue;
+            } else if !seen.insert(name.clone()) {
+                continue;
+            }
+            let current = ServiceState { pid, start, restarts: vec![] };
+            let Some(prev) = self.services.get(&name) else {
+                self.services.insert(name, current);
+                continue;
+            };
+            let running = items
+                .iter()
+                .filter(|i| i.configured && i.name == name)
+                .filter_map(started)
+                .any(|(pid, start)| prev.pid == pid && prev.start.abs_diff(start) <= 2);
+            if running {
+                continue;
+            }
+            let mut restarts = prev.restarts.clone();
+            restarts.push(now);
+            restarts.retain(|t| now.saturating_sub(*t) < HOUR);
+            let msg = if restarts.len() > 1 {
+                format!("restarted {} times in the last hour", restarts.len())
+            } else {
+                String::from("restarted since last check")
+            };
+            items[idx].alerts.push(Level::Warning, msg);
+            self.services.insert(name, ServiceState { restarts, ..current });
+        }
+        for svc in self.services.values_mut() {
+            svc.restarts.retain(|t| now.saturating_sub(*t) < HOUR);
+        }
+        if complete {
+            self.others = Some(others);
+        }
+        self.time = now;
+    }
+}
+
+pub fn update(path: &Path, items: &mut [ServiceItem], now: u64, complete: bool) -> Result<(), Error> {
+    let mut state = State::load(path)?;
+    state.update(items, now, complete);
+    state.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ProcStats;
+
+    fn item(name: &str, pid: u32, uptime: u64, configured: bool) -> ServiceItem {
+        let stats = ProcStats { uptime, ..Default::default() };
+        ServiceItem { configured, ..crate::report::tests::item(name, pid, Some(stats)) }
+    }
+
+    fn messages(items: &[ServiceItem]) -> Vec<String> {
+        items.iter().flat_map(|i| i.alerts.iter().map(|(_, m)| m.clone())).collect()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut state = State::default();
+        let mut items = [
+            item("web", 10, 100, true),
+            item("web", 11, 50, true),
+            item("sys/foo", 20, 10, false),
+        ];
+        state.update(&mut items, 1000, true);
+        assert!(messages(&items).is_empty());
+        assert_eq!(state.services["web"], ServiceState { pid: 10, start: 900, restarts: vec![] });
+
+        let mut items = [
+            item("web", 11, 110, true),
+            item("web", 10, 160, true),
+            item("sys/foo", 20, 70, false),
+            item("sys/bar", 30, 5, false),
+        ];
+        state.update(&mut items, 1060, true);
+        assert_eq!(messages(&items), ["new since last check"]);
+        assert_eq!(items[3].alerts.level(), Level::Warning);
+
+        let mut items = [item("web", 12, 10, true)];
+        state.update(&mut items, 1120, false);
+        assert_eq!(messages(&items), ["restarted since last check"]);
+        let mut items = [item("web", 13, 10, true)];
+        state.update(&mut items, 1180, false);
+        assert_eq!(messages(&items), ["restarted 2 times in the last hour"]);
+        let mut items = [item("web", 13, 3580, true)];
+        state.update(&mut items, 4750, false);
+        assert!(messages(&items).is_empty());
+        assert_eq!(state.services["web"].restarts, [1180]);
+        assert_eq!(state.others.as_ref().map(|s| s.len()), Some(2));
+    }
+
+    #[test]
+    fn test_load_save() {
+        let path = std::env::temp_dir().join(format!("upstate-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(State::load(&path).unwrap(), State::default());
+        let mut items = [item("web", 10, 100, true)];
+        update(&path, &mut items, 1000, false).unwrap();
+        let state = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(state.time, 1000);
+        assert_eq!(state.services["web"].pid, 10);
+        assert_eq!(state.others, None);
+    }
+}
@@ -0,0 +1,101 @@
+use colored::Colorize;
+use std::io::{Read, Write, stdin, stdout};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+use crate::{fmt, report};
+use upstate::conf::{Config, ServiceDisplay, SortBy};
+use upstate::report::Options;
+
+struct RawMode(Option<libc::termios>);
+
+impl RawMode {
+    fn enable() -> RawMode {
+        unsafe {
+            let mut orig: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut orig) != 0 {
+                return RawMode(None);
+            }
+            let mut raw = orig;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            RawMode(Some(orig))
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(orig) = self.0 {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &orig);
+            }
+        }
+    }
+}
+
+pub fn watch(config: &Config, opts: &mut Options, secs: u64) {
+    let interval = Duration::from_secs(secs.max(1));
+    let (tx, rx) = mpsc::channel();
+    let raw = RawMode::enable();
+    if raw.0.is_some() {
+        thread::spawn(move || {
+            let mut buf = [0u8; 1];
+            while stdin().read(&mut buf).is_ok_and(|n| n > 0) {
+                if tx.send(buf[0]).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let mut sys = System::new_all();
+    let mut refreshed = Instant::now();
+    let mut out = stdout();
+    print!("\x1b[?1049h\x1b[?25l");
+    loop {
+        // Keypresses redraw early, so rates use the actual time between refreshes
+        opts.sample = Some(refreshed.elapsed());
+        refreshed = Instant::now();
+        sys.refresh_all();
+        let mut fmt = fmt::Format::text();
+        report(&sys, &mut fmt, config, opts);
+        let sort = match opts.sort {
+            Some(SortBy::Cpu) => "cpu",
+            Some(SortBy::Rss) => "rss",
+            Some(SortBy::Uptime) => "uptime",
+            Some(SortBy::Io) => "io",
+            Some(SortBy::Pss) => "pss",
+            Some(SortBy::Uss) => "uss",
+            Some(SortBy::Swap) => "swap",
+            None => "none",
+        };
+        let services = if opts.services == ServiceDisplay::All { "complete" } else { "limited" };
+        let help = "[c]pu [m]em [t]ime [a]ll [q]uit";
+        let header = format!("Every {}s \u{2219} sort {} \u{2219} {} \u{2219} {}", secs, sort, services, help);
+        print!("\x1b[H\x1b[2J{}\n\n{}", header.white(), fmt.finish());
+        let _ = out.flush();
+        let start = Instant::now();
+        while let Some(timeout) = interval.checked_sub(start.elapsed()) {
+            match rx.recv_timeout(timeout) {
+                Ok(b'c') => opts.sort = Some(SortBy::Cpu),
+                Ok(b'm' | b'r') => opts.sort = Some(SortBy::Rss),
+                Ok(b't' | b'u') => opts.sort = Some(SortBy::Uptime),
+                Ok(b'a') if opts.services == ServiceDisplay::All => opts.services = ServiceDisplay::Required,
+                Ok(b'a') => opts.services = ServiceDisplay::All,
+                Ok(b'q' | 3 | 4) => {
+                    print!("\x1b[?25h\x1b[?1049l");
+                    let _ = out.flush();
+                    return;
+                }
+                Ok(_) => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(timeout),
+            }
+            break;
+        }
+    }
+}